@@ -0,0 +1,292 @@
+//! `curryc`, the command line compiler for curry source files.
+//!
+//! Exit codes: `0` on success, `1` if the source has errors, `2` for invalid command lines and `3` if anything else
+//! fails, e.g. reading the source or linking. `curryc run` exits with the status of the compiled program.
+//!
+//! Building executables, and so `curryc run`, links with the system c compiler driver, `cc` or the one named by
+//! `CC`. Object and assembly files need no other tools.
+
+use std::fs::{create_dir_all, remove_dir_all};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+use clap::{Parser, Subcommand, ValueEnum};
+use thiserror::Error;
+use std::str::FromStr;
+use curry_lang_language::{check_source_file, is_incomplete, CompileError, Context, LLIRCodeGenerator, OptLevel, Repl};
+
+const EXIT_SOURCE_ERROR: i32 = 1;
+const EXIT_FAILURE: i32 = 3;
+
+#[derive(Parser, Debug)]
+#[command(name = "curryc", version, about = "Compiler for the curry language")]
+struct Cli {
+    #[command(subcommand)]
+    command: Action,
+    /// Optimization level: 0, 1, 2, 3 or s for small code
+    #[arg(short = 'O', long = "opt-level", global = true, default_value = "0", value_parser = OptLevel::from_str)]
+    opt_level: OptLevel,
+}
+
+#[derive(Subcommand, Debug)]
+enum Action {
+    /// Compile a source file to an executable, object file or assembly
+    Build {
+        input: PathBuf,
+        /// Path of the output, defaults to the name of the source file with the extension of the emitted kind
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = Emit::Exe)]
+        emit: Emit,
+    },
+    /// Compile a source file and run it, exiting with the status of the program
+    Run {
+        input: PathBuf,
+        /// Execute in-process with the jit instead of building an executable
+        #[arg(long, conflicts_with = "args")]
+        jit: bool,
+        /// Arguments passed on to the program
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Report errors in a source file without generating code
+    Check {
+        input: PathBuf,
+    },
+    /// Write the llvm ir of a source file
+    EmitLlvm {
+        input: PathBuf,
+        /// Path of the ir file, defaults to the source file with `.ll` extension. `-` writes to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Start an interactive shell
+    Repl,
+}
+
+const REPL_HELP: &str = "\
+Enter statements to run them, the value of a trailing expression is printed.
+Variables and functions stay defined for later inputs, `func` blocks span lines until their braces are closed.
+
+  :ir    show the llvm ir of the last input
+  :help  show this help
+  :quit  leave the shell";
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// A linked executable
+    Exe,
+    /// A native object file
+    Obj,
+    /// Native assembly
+    Asm,
+}
+
+impl Emit {
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Emit::Exe => None,
+            Emit::Obj => Some("o"),
+            Emit::Asm => Some("s"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+enum CliError {
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl CliError {
+    fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> CliError {
+        let context = context.into();
+        move |source| CliError::Io { context, source }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Compile(CompileError::Source(_)) => EXIT_SOURCE_ERROR,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let opt_level = cli.opt_level;
+    let result = match cli.command {
+        Action::Build { input, output, emit } => {
+            let output = output.unwrap_or_else(|| default_output(&input, emit.extension()));
+            build(&input, &output, emit, opt_level).map(|_| 0)
+        },
+        Action::Run { input, jit: true, .. } => run_jit(&input, opt_level),
+        Action::Run { input, args, .. } => run(&input, &args, opt_level),
+        Action::Check { input } => check_source_file(&input).map(|_| 0).map_err(CliError::from),
+        Action::EmitLlvm { input, output } => {
+            let output = output.unwrap_or_else(|| default_output(&input, Some("ll")));
+            emit_llvm(&input, &output, opt_level).map(|_| 0)
+        },
+        Action::Repl => repl().map(|_| 0),
+    };
+
+    match result {
+        Ok(status) => exit(status),
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(error.exit_code());
+        },
+    }
+}
+
+/// The source path with its extension replaced, or removed if `extension` is `None`. If that is the source itself,
+/// e.g. for a source without extension, `.out` is appended instead of overwriting it.
+fn default_output(input: &Path, extension: Option<&str>) -> PathBuf {
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    let output = PathBuf::from(stem);
+    let output = match extension {
+        Some(extension) => output.with_extension(extension),
+        None => output,
+    };
+    if !is_same_file(input, &output) {
+        return output;
+    }
+    let mut output = output.into_os_string();
+    output.push(".out");
+    PathBuf::from(output)
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn compile<'ctx>(context: &'ctx Context, input: &Path, opt_level: OptLevel) -> Result<LLIRCodeGenerator<'ctx>, CliError> {
+    let mut code_gen = LLIRCodeGenerator::new(context).with_opt_level(opt_level);
+    code_gen.compile_source_file(input)?;
+    Ok(code_gen)
+}
+
+fn emit_llvm(input: &Path, output: &Path, opt_level: OptLevel) -> Result<(), CliError> {
+    let context = Context::create();
+    let code_gen = compile(&context, input, opt_level)?;
+    let module = code_gen.main_module().expect("compiled program has a main module");
+
+    if output == Path::new("-") {
+        print!("{}", module.print_to_string().to_string());
+        return Ok(());
+    }
+    module.print_to_file(output)
+        .map_err(|message| CliError::Io {
+            context: format!("writing {}", output.display()),
+            source: io::Error::other(message.to_string()),
+        })
+}
+
+fn build(input: &Path, output: &Path, emit: Emit, opt_level: OptLevel) -> Result<(), CliError> {
+    let context = Context::create();
+    let code_gen = compile(&context, input, opt_level)?;
+
+    match emit {
+        Emit::Exe => code_gen.build_executable(output)?,
+        Emit::Obj => code_gen.write_object_file(output)?,
+        Emit::Asm => code_gen.write_assembly_file(output)?,
+    }
+    Ok(())
+}
+
+fn run(input: &Path, args: &[String], opt_level: OptLevel) -> Result<i32, CliError> {
+    let build_dir = std::env::temp_dir().join(format!("curryc-{}", std::process::id()));
+    create_dir_all(&build_dir).map_err(CliError::io(format!("creating {}", build_dir.display())))?;
+
+    let binary = build_dir.join(default_output(input, None));
+    let status = build(input, &binary, Emit::Exe, opt_level)
+        .and_then(|_| Command::new(&binary)
+            .args(args)
+            .status()
+            .map_err(CliError::io(format!("running {}", binary.display()))));
+    let _ = remove_dir_all(&build_dir);
+
+    Ok(exit_status(status?))
+}
+
+fn run_jit(input: &Path, opt_level: OptLevel) -> Result<i32, CliError> {
+    let context = Context::create();
+    let code_gen = compile(&context, input, opt_level)?;
+    Ok(code_gen.execute_main()?)
+}
+
+fn repl() -> Result<(), CliError> {
+    let context = Context::create();
+    let mut repl = Repl::new(&context)?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+
+    loop {
+        print!("{}", if input.is_empty() { "curry> " } else { "  ...> " });
+        io::stdout().flush().map_err(CliError::io("writing prompt"))?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(CliError::io("reading input"))?,
+            None => return Ok(()),
+        };
+
+        if input.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":q" => return Ok(()),
+                ":help" => {
+                    println!("{}", REPL_HELP);
+                    continue;
+                },
+                ":ir" => {
+                    match repl.last_ir() {
+                        Some(ir) => print!("{}", ir),
+                        None => println!("nothing compiled yet"),
+                    }
+                    continue;
+                },
+                command if command.starts_with(':') => {
+                    eprintln!("unknown command {}, see :help", command);
+                    continue;
+                },
+                _ => {},
+            }
+        }
+
+        input.push_str(&line);
+        input.push('\n');
+        if is_incomplete(&input) {
+            continue;
+        }
+
+        match repl.eval(&input) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {},
+            Err(error) => eprintln!("{}", error),
+        }
+        input.clear();
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(EXIT_FAILURE)
+}
+
+#[cfg(not(unix))]
+fn exit_status(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(EXIT_FAILURE)
+}
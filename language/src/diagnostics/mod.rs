@@ -0,0 +1,177 @@
+//! User facing compile errors, rendered with their location and the offending source line.
+
+mod render;
+
+use std::fmt;
+use thiserror::Error;
+use crate::parser::curry_pest::Rule;
+use crate::parser::errors::{IllegalSourceState, InvalidParserState};
+use crate::parser::Span;
+use crate::typeck::TypeError;
+
+/// A source text together with the name it is reported under, usually its path.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        SourceFile { name: name.into(), text: text.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Where the problem is, `None` if it cannot be attributed to a part of the source.
+    pub span: Option<Span>,
+    /// Short explanation printed next to the caret.
+    pub label: Option<String>,
+    pub help: Option<String>,
+    /// A related location shown below the problem.
+    pub note: Option<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic { message: message.into(), span: None, label: None, help: None, note: None }
+    }
+
+    pub fn spanned(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic { span: Some(span), ..Diagnostic::new(message) }
+    }
+
+    pub fn with_label(self, label: impl Into<String>) -> Self {
+        Diagnostic { label: Some(label.into()), ..self }
+    }
+
+    pub fn with_help(self, help: impl Into<String>) -> Self {
+        Diagnostic { help: Some(help.into()), ..self }
+    }
+
+    pub fn with_note(self, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic { note: Some(Note { message: message.into(), span }), ..self }
+    }
+
+    pub fn render(&self, file: &SourceFile) -> String {
+        render::render(self, file)
+    }
+}
+
+impl From<TypeError> for Diagnostic {
+    fn from(error: TypeError) -> Self {
+        let mut diagnostic = Diagnostic::spanned(error.message, error.span);
+        if let Some(help) = error.help {
+            diagnostic = diagnostic.with_help(help);
+        }
+        if let Some((message, span)) = error.note {
+            diagnostic = diagnostic.with_note(message, span);
+        }
+        diagnostic
+    }
+}
+
+impl From<InvalidParserState> for Diagnostic {
+    fn from(error: InvalidParserState) -> Self {
+        match error {
+            InvalidParserState::Syntax(mut error) => {
+                let span = match error.location {
+                    pest::error::InputLocation::Pos(position) => Span::new(position, position),
+                    pest::error::InputLocation::Span((start, end)) => Span::new(start, end),
+                };
+                // comments may appear almost anywhere, listing them as expected is just noise
+                if let pest::error::ErrorVariant::ParsingError { positives, .. } = &mut error.variant {
+                    positives.retain(|rule| *rule != Rule::COMMENT);
+                }
+                let error = error.renamed_rules(describe_rule);
+                Diagnostic::spanned("syntax error", span).with_label(error.variant.message())
+            },
+            InvalidParserState::IllegalSourceState(IllegalSourceState::IntegerOutOfRange { literal, span }) => {
+                Diagnostic::spanned(format!("integer literal {} is out of range", literal), span)
+                    .with_help("integers are signed 64 bit values")
+            },
+            InvalidParserState::IllegalSourceState(IllegalSourceState::UnknownEscape { sequence, span }) => {
+                Diagnostic::spanned(format!("unknown escape sequence `{}`", sequence), span)
+                    .with_help(r#"valid escapes are \" \\ \n \r \t \b \f \0, \uXXXX and \u{X} with up to six hex digits"#)
+            },
+            InvalidParserState::IllegalSourceState(IllegalSourceState::InvalidUnicodeEscape { sequence, span }) => {
+                Diagnostic::spanned(format!("invalid unicode escape `{}`", sequence), span)
+                    .with_help("unicode escapes are `\\uXXXX` or `\\u{X}` with up to six hex digits of a valid code point")
+            },
+            InvalidParserState::IllegalSourceState(state) => Diagnostic::new(state.to_string()),
+        }
+    }
+}
+
+/// Names of grammar rules as they appear in syntax errors.
+fn describe_rule(rule: &Rule) -> String {
+    match rule {
+        Rule::symbol_ref => "identifier",
+        Rule::expression => "expression",
+        Rule::integer_literal => "integer",
+        Rule::string_literal => "string",
+        Rule::type_name => "type",
+        Rule::fn_args | Rule::fn_params => "`(`",
+        Rule::function_body => "`{`",
+        Rule::function_declaration => "function declaration",
+        Rule::entry_definition => "`entry: <function>`",
+        Rule::return_type => "`->`",
+        Rule::boolean_literal => "boolean",
+        Rule::block => "`{`",
+        Rule::if_expression | Rule::IF_KEYWORD => "`if`",
+        Rule::lambda | Rule::lambda_params => "lambda",
+        Rule::struct_declaration | Rule::STRUCT_KEYWORD => "struct declaration",
+        Rule::struct_literal => "struct literal",
+        Rule::struct_field | Rule::field_init => "field",
+        Rule::field => "`.`",
+        Rule::while_statement => "`while`",
+        Rule::for_statement => "`for`",
+        Rule::IN_KEYWORD => "`in`",
+        Rule::range => "range",
+        Rule::negate | Rule::subtract => "`-`",
+        Rule::not => "`!`",
+        Rule::pipe => "`|>`",
+        Rule::or => "`||`",
+        Rule::and => "`&&`",
+        Rule::equal => "`==`",
+        Rule::not_equal => "`!=`",
+        Rule::less => "`<`",
+        Rule::less_equal => "`<=`",
+        Rule::greater => "`>`",
+        Rule::greater_equal => "`>=`",
+        Rule::add => "`+`",
+        Rule::multiply => "`*`",
+        Rule::divide => "`/`",
+        Rule::modulo => "`%`",
+        Rule::EOI => "end of file",
+        other => return format!("{:?}", other),
+    }.to_string()
+}
+
+/// All errors found in the compiled source, rendered against the source file when displayed.
+#[derive(Error, Debug)]
+pub struct SourceError {
+    pub file: SourceFile,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rendered: Vec<String> = self.diagnostics.iter()
+            .map(|diagnostic| diagnostic.render(&self.file))
+            .collect();
+        write!(f, "{}", rendered.join("\n"))?;
+        if self.diagnostics.len() > 1 {
+            write!(f, "\nerror: aborting due to {} previous errors\n", self.diagnostics.len())?;
+        }
+        Ok(())
+    }
+}
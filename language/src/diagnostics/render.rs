@@ -0,0 +1,128 @@
+use std::fmt::Write;
+use crate::diagnostics::{Diagnostic, SourceFile};
+use crate::parser::Span;
+
+/// Renders in the style of rustc:
+///
+/// ```text
+/// error: usage of unknown variable 'name'
+///  --> hello.cry:3:12
+///   |
+/// 3 |     printf(name)
+///   |            ^^^^
+///   = help: assign a value to 'name' before using it
+/// ```
+pub fn render(diagnostic: &Diagnostic, file: &SourceFile) -> String {
+    let mut out = String::new();
+    writeln!(out, "error: {}", diagnostic.message).unwrap();
+
+    let span = match diagnostic.span {
+        Some(span) => span,
+        None => {
+            writeln!(out, " --> {}", file.name).unwrap();
+            if let Some(help) = &diagnostic.help {
+                writeln!(out, "  = help: {}", help).unwrap();
+            }
+            return out;
+        },
+    };
+
+    let gutter = render_snippet(&mut out, file, span, diagnostic.label.as_deref());
+    if let Some(note) = &diagnostic.note {
+        writeln!(out, "note: {}", note.message).unwrap();
+        render_snippet(&mut out, file, note.span, None);
+    }
+
+    if let Some(help) = &diagnostic.help {
+        writeln!(out, "{} = help: {}", gutter, help).unwrap();
+    }
+    out
+}
+
+/// Writes the location and the source line with the span underlined, returns the gutter in front of the `|`.
+fn render_snippet(out: &mut String, file: &SourceFile, span: Span, label: Option<&str>) -> String {
+    let text = file.text.as_str();
+    let start = span.start.min(text.len());
+    let (line, column) = span.line_col(text);
+    let line_start = text[..start].rfind('\n').map_or(0, |it| it + 1);
+    let line_end = text[start..].find('\n').map_or(text.len(), |it| start + it);
+    let source_line = text[line_start..line_end].trim_end_matches('\r');
+
+    let line_number = line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    writeln!(out, "{}--> {}:{}:{}", gutter, file.name, line, column).unwrap();
+    writeln!(out, "{} |", gutter).unwrap();
+    writeln!(out, "{} | {}", line_number, source_line).unwrap();
+
+    // keep tabs in front of the caret, so it lines up however the terminal expands them
+    let indent: String = text[line_start..start].chars()
+        .map(|it| if it == '\t' { '\t' } else { ' ' })
+        .collect();
+    let end = span.end.clamp(start, line_end.max(start));
+    let width = text[start..end].chars().count().max(1);
+    write!(out, "{} | {}{}", gutter, indent, "^".repeat(width)).unwrap();
+    match label {
+        Some(label) => writeln!(out, " {}", label).unwrap(),
+        None => writeln!(out).unwrap(),
+    }
+    gutter
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_underlines_the_span() {
+        let file = SourceFile::new("hello.cry", "name = \"Chuck\"\nprintf(nmae)\n");
+        let diagnostic = Diagnostic::spanned("usage of unknown variable 'nmae'", Span::new(22, 26))
+            .with_help("assign a value to 'nmae' before using it");
+
+        assert_eq!(
+            "error: usage of unknown variable 'nmae'\n \
+              --> hello.cry:2:8\n  \
+               |\n\
+             2 | printf(nmae)\n  \
+               |        ^^^^\n  \
+               = help: assign a value to 'nmae' before using it\n",
+            render(&diagnostic, &file)
+        );
+    }
+
+    #[test]
+    fn it_keeps_tabs_before_the_caret() {
+        let file = SourceFile::new("tabs.cry", "func main() {\n\tprintf(1 +)\n}");
+        let diagnostic = Diagnostic::spanned("syntax error", Span::new(25, 25)).with_label("expected expression");
+
+        assert_eq!(
+            "error: syntax error\n \
+              --> tabs.cry:2:12\n  \
+               |\n\
+             2 | \tprintf(1 +)\n  \
+               | \t          ^ expected expression\n",
+            render(&diagnostic, &file)
+        );
+    }
+
+    #[test]
+    fn it_shows_the_note_below_the_problem() {
+        let file = SourceFile::new("count.cry", "let count = 0\ncount = 1\n");
+        let diagnostic = Diagnostic::spanned("cannot assign twice to immutable variable 'count'", Span::new(14, 19))
+            .with_note("'count' is defined here", Span::new(4, 9));
+
+        assert_eq!(
+            "error: cannot assign twice to immutable variable 'count'\n \
+              --> count.cry:2:1\n  \
+               |\n\
+             2 | count = 1\n  \
+               | ^^^^^\n\
+             note: 'count' is defined here\n \
+              --> count.cry:1:5\n  \
+               |\n\
+             1 | let count = 0\n  \
+               |     ^^^^^\n",
+            render(&diagnostic, &file)
+        );
+    }
+}
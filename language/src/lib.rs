@@ -1,8 +1,16 @@
-extern crate core;
-
-mod ll_code_gen;
-
-mod parser;
-
-pub use crate::ll_code_gen::LLIRCodeGenerator;
+extern crate core;
+
+mod diagnostics;
+
+mod ll_code_gen;
+
+mod typeck;
+
+mod parser;
+
+mod repl;
+
+pub use crate::diagnostics::{Diagnostic, SourceError, SourceFile};
+pub use crate::ll_code_gen::{check_source_file, CodegenError, CompileError, LLIRCodeGenerator, OptLevel};
+pub use crate::repl::{is_incomplete, Repl};
 pub use inkwell::context::Context;
\ No newline at end of file
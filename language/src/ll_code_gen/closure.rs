@@ -0,0 +1,186 @@
+use std::convert::TryFrom;
+use inkwell::AddressSpace;
+use inkwell::module::Linkage;
+use inkwell::types::StructType;
+use inkwell::values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallableValue, FunctionValue, PointerValue, StructValue};
+use crate::ll_code_gen::{CodegenError, CodegenResult};
+use crate::ll_code_gen::expression::generate_value;
+use crate::ll_code_gen::function_generator::FunctionGenerator;
+use crate::typeck::{FunctionType, Type, TypedExpr, TypedExprKind, TypedParam, TypedStmt};
+
+/// A declared function as a value. Its closure has no environment, the closure function just forwards the arguments.
+pub fn generate_function_reference<'gen>(name: &str, ty: &Type, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let signature = function_signature(ty)?;
+    let module = scope.parent;
+    let function = *module.functions.borrow().get(name)
+        .ok_or_else(|| CodegenError::UndefinedFunction(name.to_string()))?;
+
+    // every reference to the same function shares the closure function
+    let closure_name = format!("{}.closure", name);
+    let closure_function = match module.module.get_function(closure_name.as_str()) {
+        Some(closure_function) => closure_function,
+        None => {
+            let closure_function = add_closure_function(closure_name.as_str(), signature, scope)?;
+            let fn_gen = FunctionGenerator::create_generator(module, closure_function);
+            let args = closure_function.get_param_iter().skip(1).collect::<Vec<_>>();
+            let value = fn_gen.build_direct_call(function, &args, name);
+            fn_gen.build_return_value(value)?;
+            fn_gen.complete().map_err(|error| error.in_function(closure_name.as_str()))?;
+            closure_function
+        },
+    };
+
+    let environment = module.environment_type().const_null();
+    build_closure(closure_function, environment, signature, scope)
+}
+
+/// Captures the callee and the arguments in an environment on the heap. The closure function of the result calls
+/// the callee with the captured arguments followed by its own.
+pub fn generate_partial_application<'gen>(callee: TypedExpr, args: Vec<TypedExpr>, ty: &Type, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let remaining = function_signature(ty)?;
+    let name = match &callee.kind {
+        TypedExprKind::Variable(name) | TypedExprKind::Function(name) => format!("{}.partial", name),
+        _ => "partial".to_string(),
+    };
+    let captured = std::iter::once(callee)
+        .chain(args)
+        .map(|value| generate_value(value, scope))
+        .collect::<CodegenResult<Vec<BasicValueEnum>>>()?;
+    let (environment, environment_type) = build_environment(&captured, scope);
+
+    let closure_function = add_closure_function(name.as_str(), remaining, scope)?;
+    let fn_gen = FunctionGenerator::create_generator(scope.parent, closure_function);
+    let mut values = load_environment(closure_function, environment_type, &fn_gen);
+    let callee = values.remove(0).into_struct_value();
+    let args = values.into_iter()
+        .map(BasicMetadataValueEnum::from)
+        .chain(closure_function.get_param_iter().skip(1).map(BasicMetadataValueEnum::from))
+        .collect();
+    let value = call_closure(callee, args, &fn_gen);
+    fn_gen.build_return_value(value)?;
+    fn_gen.complete().map_err(|error| error.in_function(name.as_str()))?;
+
+    build_closure(closure_function, environment, remaining, scope)
+}
+
+/// Generates the body of a lambda as closure function. The captured variables are copied to its environment and
+/// from there to variables of the closure function.
+pub fn generate_lambda<'gen>(params: Vec<TypedParam>, body: Vec<TypedStmt>, captures: Vec<String>, ty: &Type, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let signature = function_signature(ty)?;
+    let captured = captures.iter()
+        .map(|name| Ok(scope.builder.build_load(scope.resolve_symbol(name)?, name)))
+        .collect::<CodegenResult<Vec<BasicValueEnum>>>()?;
+    let (environment, environment_type) = build_environment(&captured, scope);
+
+    let closure_function = add_closure_function("lambda", signature, scope)?;
+    let fn_gen = FunctionGenerator::create_generator(scope.parent, closure_function);
+    fn_gen.bind_values(load_environment(closure_function, environment_type, &fn_gen), captures);
+    fn_gen.bind_values(closure_function.get_param_iter().skip(1), params.into_iter().map(|param| param.name));
+    fn_gen.generate_statements(body)
+        .and_then(|_| fn_gen.complete())
+        .map_err(|error| error.in_function("lambda"))?;
+
+    build_closure(closure_function, environment, signature, scope)
+}
+
+/// Calls a function value with all its remaining arguments.
+pub fn generate_closure_call<'gen>(callee: TypedExpr, args: Vec<TypedExpr>, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<Option<BasicValueEnum<'gen>>> {
+    let ty = callee.ty.clone();
+    let closure = match generate_value(callee, scope)? {
+        BasicValueEnum::StructValue(closure) => closure,
+        _ => return Err(CodegenError::UnsupportedType(ty)),
+    };
+    let args = args.into_iter()
+        .map(|arg| generate_value(arg, scope).map(BasicMetadataValueEnum::from))
+        .collect::<CodegenResult<Vec<_>>>()?;
+
+    Ok(call_closure(closure, args, scope))
+}
+
+fn call_closure<'gen>(closure: StructValue<'gen>, args: Vec<BasicMetadataValueEnum<'gen>>, scope: &FunctionGenerator<'gen,'_,'_>) -> Option<BasicValueEnum<'gen>> {
+    let function = scope.builder.build_extract_value(closure, 0, "fn")
+        .expect("closure has a function")
+        .into_pointer_value();
+    let environment = scope.builder.build_extract_value(closure, 1, "env")
+        .expect("closure has an environment");
+    let function = CallableValue::try_from(function).expect("closure function is a function pointer");
+
+    let args = std::iter::once(environment.into())
+        .chain(args)
+        .collect::<Vec<BasicMetadataValueEnum>>();
+    scope.builder.build_call(function, &args, "call").try_as_basic_value().left()
+}
+
+/// Copies the values to a new environment, a null pointer if there is nothing to capture. Environments live on the
+/// heap, so closures can outlive the function creating them. They are never freed.
+fn build_environment<'gen>(captured: &[BasicValueEnum<'gen>], scope: &FunctionGenerator<'gen,'_,'_>) -> (PointerValue<'gen>, StructType<'gen>) {
+    let module = scope.parent;
+    let field_types = captured.iter().map(|value| value.get_type()).collect::<Vec<_>>();
+    let environment_type = module.parent.context.struct_type(&field_types, false);
+    if captured.is_empty() {
+        return (module.environment_type().const_null(), environment_type);
+    }
+
+    let size = environment_type.size_of().expect("environments consist of sized values");
+    let environment = scope.builder.build_call(malloc(scope), &[size.into()], "env")
+        .try_as_basic_value().left()
+        .expect("malloc returns a pointer")
+        .into_pointer_value();
+    let fields = scope.builder
+        .build_pointer_cast(environment, environment_type.ptr_type(AddressSpace::default()), "captured");
+    for (index, value) in captured.iter().enumerate() {
+        let field = scope.builder.build_struct_gep(fields, index as u32, "captured")
+            .expect("environment has a field for each captured value");
+        scope.builder.build_store(field, *value);
+    }
+    (environment, environment_type)
+}
+
+/// Loads the captured values from the environment passed to the closure function.
+fn load_environment<'gen>(closure_function: FunctionValue<'gen>, environment_type: StructType<'gen>, fn_gen: &FunctionGenerator<'gen,'_,'_>) -> Vec<BasicValueEnum<'gen>> {
+    let environment = closure_function.get_first_param()
+        .expect("closure functions take the environment")
+        .into_pointer_value();
+    let fields = fn_gen.builder
+        .build_pointer_cast(environment, environment_type.ptr_type(AddressSpace::default()), "env");
+    (0..environment_type.count_fields())
+        .map(|index| {
+            let field = fn_gen.builder.build_struct_gep(fields, index, "captured")
+                .expect("environment has a field for each captured value");
+            fn_gen.builder.build_load(field, "captured")
+        })
+        .collect()
+}
+
+fn malloc<'gen>(scope: &FunctionGenerator<'gen,'_,'_>) -> FunctionValue<'gen> {
+    let module = scope.parent;
+    module.module.get_function("malloc").unwrap_or_else(|| {
+        let size_type = module.parent.context.i64_type();
+        let fn_type = module.environment_type().fn_type(&[size_type.into()], false);
+        module.module.add_function("malloc", fn_type, Some(Linkage::External))
+    })
+}
+
+/// Closure functions are private to the module, so each repl input can have its own.
+fn add_closure_function<'gen>(name: &str, signature: &FunctionType, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<FunctionValue<'gen>> {
+    let module = scope.parent;
+    let fn_type = module.closure_fn_type(signature)?;
+    Ok(module.module.add_function(name, fn_type, Some(Linkage::Private)))
+}
+
+fn build_closure<'gen>(function: FunctionValue<'gen>, environment: PointerValue<'gen>, signature: &FunctionType, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let closure_type = scope.parent.closure_type(signature)?;
+    let function = function.as_global_value().as_pointer_value();
+    let closure = scope.builder.build_insert_value(closure_type.get_undef(), function, 0, "closure")
+        .expect("closure has a function");
+    let closure = scope.builder.build_insert_value(closure, environment, 1, "closure")
+        .expect("closure has an environment");
+    Ok(closure.as_basic_value_enum())
+}
+
+fn function_signature(ty: &Type) -> CodegenResult<&FunctionType> {
+    match ty {
+        Type::Function(signature) => Ok(signature),
+        other => Err(CodegenError::UnsupportedType(other.clone())),
+    }
+}
@@ -0,0 +1,172 @@
+use inkwell::basic_block::BasicBlock;
+use inkwell::IntPredicate;
+use inkwell::values::{BasicValue, BasicValueEnum};
+use crate::ll_code_gen::CodegenResult;
+use crate::ll_code_gen::expression::{generate_integer, generate_value};
+use crate::ll_code_gen::function_generator::FunctionGenerator;
+use crate::typeck::{BinaryOp, Type, TypedBlock, TypedExpr, TypedStmt, TypedStmtKind};
+
+/// Where `break` and `continue` of a loop jump to.
+#[derive(Debug, Clone, Copy)]
+pub struct LoopTargets<'gen> {
+    pub break_bb: BasicBlock<'gen>,
+    pub continue_bb: BasicBlock<'gen>,
+}
+
+/// A value flowing into a merge block, `None` for unit, and the block it comes from.
+type Incoming<'gen> = (Option<BasicValueEnum<'gen>>, BasicBlock<'gen>);
+
+/// Generates an `if`, its value is merged from the branches with a phi node.
+pub fn generate_if<'gen>(condition: TypedExpr, then_block: TypedBlock, else_block: Option<TypedBlock>, ty: &Type, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<Option<BasicValueEnum<'gen>>> {
+    let condition = generate_integer(condition, scope)?;
+    let then_bb = scope.append_block("then");
+    let else_bb = else_block.as_ref().map(|_| scope.append_block("else"));
+    let merge_bb = scope.append_block("endif");
+    scope.builder.build_conditional_branch(condition, then_bb, else_bb.unwrap_or(merge_bb));
+
+    let with_value = !ty.is_unit();
+    let mut incoming = Vec::new();
+    scope.builder.position_at_end(then_bb);
+    incoming.extend(generate_branch(then_block, with_value, merge_bb, scope)?);
+    if let (Some(else_block), Some(else_bb)) = (else_block, else_bb) {
+        scope.builder.position_at_end(else_bb);
+        incoming.extend(generate_branch(else_block, with_value, merge_bb, scope)?);
+    } else {
+        // the condition branches to the merge block directly
+        scope.builder.position_at_end(merge_bb);
+        return Ok(None);
+    }
+
+    if incoming.is_empty() {
+        // every branch returned, nothing continues after the if
+        unsafe {
+            merge_bb.delete().expect("merge block is unused");
+        }
+        return Ok(None);
+    }
+
+    scope.builder.position_at_end(merge_bb);
+    let values = incoming.into_iter()
+        .filter_map(|(value, block)| value.map(|value| (value, block)))
+        .collect::<Vec<_>>();
+    match values.first() {
+        Some((first, _)) if with_value => {
+            let phi = scope.builder.build_phi(first.get_type(), "ifvalue");
+            for (value, block) in values.iter() {
+                phi.add_incoming(&[(value as &dyn BasicValue, *block)]);
+            }
+            Ok(Some(phi.as_basic_value()))
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Generates the block and branches to `merge_bb` unless it returned, `None` if it did.
+fn generate_branch<'gen>(block: TypedBlock, with_value: bool, merge_bb: BasicBlock<'gen>, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<Option<Incoming<'gen>>> {
+    let mut statements = block.statements;
+    let value = match statements.last() {
+        Some(TypedStmt { kind: TypedStmtKind::Expression(_), .. }) if with_value => match statements.pop() {
+            Some(TypedStmt { kind: TypedStmtKind::Expression(value), .. }) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let value = scope.in_scope(|| {
+        scope.generate_statements(statements)?;
+        value.map(|value| generate_value(value, scope)).transpose()
+    })?;
+    if scope.is_terminated() {
+        return Ok(None);
+    }
+    let block = scope.current_block();
+    scope.builder.build_unconditional_branch(merge_bb);
+    Ok(Some((value, block)))
+}
+
+/// Generates a `while` loop, the header checks the condition before every iteration.
+pub fn generate_while<'gen>(condition: TypedExpr, body: TypedBlock, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<()> {
+    let header_bb = scope.append_block("while.cond");
+    let body_bb = scope.append_block("while.body");
+    let exit_bb = scope.append_block("while.end");
+    scope.builder.build_unconditional_branch(header_bb);
+
+    scope.builder.position_at_end(header_bb);
+    let condition = generate_integer(condition, scope)?;
+    scope.builder.build_conditional_branch(condition, body_bb, exit_bb);
+
+    scope.builder.position_at_end(body_bb);
+    scope.generate_loop_body(body.statements, LoopTargets { break_bb: exit_bb, continue_bb: header_bb })?;
+    if !scope.is_terminated() {
+        scope.builder.build_unconditional_branch(header_bb);
+    }
+
+    scope.builder.position_at_end(exit_bb);
+    Ok(())
+}
+
+/// Generates a `for` loop over a range. The counter lives in a stack slot that is incremented by a separate step
+/// block, so `continue` still advances it.
+pub fn generate_for<'gen>(variable: String, start: TypedExpr, end: TypedExpr, body: TypedBlock, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<()> {
+    let start = generate_integer(start, scope)?;
+    let end = generate_integer(end, scope)?;
+    let counter = scope.build_entry_alloca(start.get_type().into(), variable.as_str());
+    scope.builder.build_store(counter, start);
+
+    let header_bb = scope.append_block("for.cond");
+    let body_bb = scope.append_block("for.body");
+    let step_bb = scope.append_block("for.step");
+    let exit_bb = scope.append_block("for.end");
+    scope.builder.build_unconditional_branch(header_bb);
+
+    scope.builder.position_at_end(header_bb);
+    let current = scope.builder.build_load(counter, variable.as_str()).into_int_value();
+    let in_range = scope.builder.build_int_compare(IntPredicate::SLT, current, end, "inrange");
+    scope.builder.build_conditional_branch(in_range, body_bb, exit_bb);
+
+    scope.builder.position_at_end(body_bb);
+    // the counter is only visible inside the loop
+    scope.in_scope(|| {
+        scope.define_symbol(variable.clone(), counter);
+        scope.generate_loop_body(body.statements, LoopTargets { break_bb: exit_bb, continue_bb: step_bb })
+    })?;
+    if !scope.is_terminated() {
+        scope.builder.build_unconditional_branch(step_bb);
+    }
+
+    scope.builder.position_at_end(step_bb);
+    let current = scope.builder.build_load(counter, variable.as_str()).into_int_value();
+    let next = scope.builder.build_int_add(current, current.get_type().const_int(1, false), "next");
+    scope.builder.build_store(counter, next);
+    scope.builder.build_unconditional_branch(header_bb);
+
+    scope.builder.position_at_end(exit_bb);
+    Ok(())
+}
+
+/// Generates `&&` and `||`, which only evaluate their right operand if the left one does not decide the result.
+pub fn generate_logical_operation<'gen>(op: BinaryOp, lhs: TypedExpr, rhs: TypedExpr, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let is_and = op == BinaryOp::And;
+    let lhs = generate_integer(lhs, scope)?;
+    let lhs_bb = scope.current_block();
+    let rhs_bb = scope.append_block(if is_and { "and.rhs" } else { "or.rhs" });
+    let merge_bb = scope.append_block(if is_and { "and.end" } else { "or.end" });
+    if is_and {
+        scope.builder.build_conditional_branch(lhs, rhs_bb, merge_bb);
+    } else {
+        scope.builder.build_conditional_branch(lhs, merge_bb, rhs_bb);
+    }
+
+    scope.builder.position_at_end(rhs_bb);
+    let rhs = generate_integer(rhs, scope)?;
+    let rhs_end_bb = scope.current_block();
+    scope.builder.build_unconditional_branch(merge_bb);
+
+    scope.builder.position_at_end(merge_bb);
+    let bool_type = scope.parent.parent.context.bool_type();
+    // reaching the merge block from the left operand means it decided the result: false for `&&`, true for `||`
+    let decided = bool_type.const_int(!is_and as u64, false);
+    let phi = scope.builder.build_phi(bool_type, op.symbol());
+    phi.add_incoming(&[(&decided, lhs_bb), (&rhs, rhs_end_bb)]);
+    Ok(phi.as_basic_value())
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use crate::diagnostics::SourceError;
+use crate::typeck::Type;
+
+pub type CodegenResult<T> = Result<T, CodegenError>;
+
+/// Failures while lowering a checked program to llvm ir. These indicate inconsistencies between type checking and
+/// code generation rather than mistakes in the source, which are reported as [`SourceError`] before codegen starts.
+#[derive(Error, Debug)]
+pub enum CodegenError {
+    #[error("function '{0}' is not defined")]
+    UndefinedFunction(String),
+    #[error("struct '{0}' is not defined")]
+    UndefinedStruct(String),
+    #[error("usage of unknown variable '{0}'")]
+    UnknownVariable(String),
+    #[error("values of type {0} are not supported")]
+    UnsupportedType(Type),
+    #[error("expression of type {0} has no value")]
+    MissingValue(Type),
+    #[error("exit status must be an integer")]
+    InvalidExitStatus,
+    #[error("`break` or `continue` outside of a loop")]
+    JumpOutsideOfLoop,
+    #[error("generated invalid llvm ir for function '{0}'")]
+    InvalidFunction(String),
+    #[error("in function '{name}': {source}")]
+    InFunction {
+        name: String,
+        #[source]
+        source: Box<CodegenError>,
+    },
+}
+
+impl CodegenError {
+    pub fn in_function(self, name: &str) -> CodegenError {
+        CodegenError::InFunction { name: name.to_string(), source: Box::new(self) }
+    }
+}
+
+/// Everything that can go wrong compiling a source file.
+#[derive(Error, Debug)]
+pub enum CompileError {
+    #[error("reading {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Source(#[from] SourceError),
+    #[error("code generation failed: {0}")]
+    Codegen(#[from] CodegenError),
+    #[error("native code generation failed: {0}")]
+    Target(String),
+    #[error("jit execution failed: {0}")]
+    Jit(String),
+    #[error("linking {} failed: {reason}", path.display())]
+    Link {
+        path: PathBuf,
+        reason: String,
+    },
+}
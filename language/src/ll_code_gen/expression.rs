@@ -1,72 +1,140 @@
-use anyhow::*;
-use inkwell::values::{BasicMetadataValueEnum, BasicValue};
-use pest::iterators::Pair;
+use inkwell::IntPredicate;
+use inkwell::values::{BasicValue, BasicValueEnum, FunctionValue, IntValue};
+use crate::ll_code_gen::{CodegenError, CodegenResult};
+use crate::ll_code_gen::closure::{generate_closure_call, generate_function_reference, generate_lambda, generate_partial_application};
+use crate::ll_code_gen::control_flow::{generate_if, generate_logical_operation};
 use crate::ll_code_gen::function_generator::FunctionGenerator;
-use crate::parser::curry_pest::{Rule, PairHelper};
+use crate::ll_code_gen::structs::{generate_field_access, generate_struct_literal};
+use crate::typeck::{BinaryOp, Literal, TypedExpr, TypedExprKind, UnaryOp};
 
-pub fn generate_expression<'gen>(pair: Pair<Rule>, scope: &FunctionGenerator<'gen,'_,'_>) -> Result<BasicMetadataValueEnum<'gen>> {
-    match pair.as_rule() {
-        Rule::string_literal => {
-            let string_expr : StringConstant = pair.into();
-            string_expr.generate(scope)
+const TRAP_FN_NAME: &str = "llvm.trap";
+
+/// Generates the expression, `None` for expressions of type unit.
+pub fn generate_expression<'gen>(expression: TypedExpr, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<Option<BasicValueEnum<'gen>>> {
+    let value = match expression.kind {
+        TypedExprKind::Literal(literal) => generate_literal(literal, scope),
+        TypedExprKind::Variable(name) => resolve_variable(name.as_str(), scope)?,
+        TypedExprKind::Function(name) => generate_function_reference(name.as_str(), &expression.ty, scope)?,
+        TypedExprKind::Call { function, args } => return scope.generate_function_call(function, args),
+        TypedExprKind::Apply { callee, args } => return generate_closure_call(*callee, args, scope),
+        TypedExprKind::Partial { callee, args } => generate_partial_application(*callee, args, &expression.ty, scope)?,
+        TypedExprKind::Unary { op, operand } => {
+            let operand = generate_integer(*operand, scope)?;
+            generate_unary_operation(op, operand, scope)
         },
-        Rule::symbol_ref => {
-            let variable_expr: VariableValue = pair.into();
-            variable_expr.resolve_variable(scope)
-        }
-        Rule::function_call => todo!("generate_expression -> function_call"),
-        it => unreachable!("unexpected expression {}", it),
-    }
+        TypedExprKind::Binary { op, lhs, rhs } if op.is_logical() => generate_logical_operation(op, *lhs, *rhs, scope)?,
+        TypedExprKind::Binary { op, lhs, rhs } => {
+            let lhs = generate_integer(*lhs, scope)?;
+            let rhs = generate_integer(*rhs, scope)?;
+            generate_binary_operation(lhs, op, rhs, scope)
+        },
+        TypedExprKind::If { condition, then_block, else_block } => {
+            return generate_if(*condition, then_block, else_block, &expression.ty, scope);
+        },
+        TypedExprKind::Lambda { params, body, captures, .. } => generate_lambda(params, body, captures, &expression.ty, scope)?,
+        TypedExprKind::StructLiteral { name, fields } => generate_struct_literal(name, fields, scope)?,
+        TypedExprKind::Field { value, index } => generate_field_access(*value, index, scope)?,
+    };
+    Ok(Some(value))
 }
 
-#[derive(derive_more::From)]
-struct VariableValue<'i> {
-    pair: Pair<'i,Rule>
+pub fn generate_value<'gen>(expression: TypedExpr, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let ty = expression.ty.clone();
+    generate_expression(expression, scope)?
+        .ok_or(CodegenError::MissingValue(ty))
 }
-impl <'i> VariableValue<'i> {
-    fn resolve_variable<'gen>(&self, scope: &FunctionGenerator<'gen,'_,'_>) -> Result<BasicMetadataValueEnum<'gen>> {
-        let context = scope.parent.parent.context;
-        let variable_name = self.pair.as_str();
-        let symbols = scope.symbols.borrow();
-        let string_pointer = *symbols.get(variable_name)
-            .with_context(|| format!("usage of unknown variable '{}'", variable_name))?;
-        let i8_type = context.i8_type();
-        let indices = [i8_type.const_zero(), i8_type.const_zero()];
-        unsafe {
-            let pointer_value = scope.builder.build_in_bounds_gep(string_pointer, &indices, "tmp");
-            Ok(BasicMetadataValueEnum::from(pointer_value.as_basic_value_enum()))
-        }
+
+/// Generates an int or bool value.
+pub fn generate_integer<'gen>(expression: TypedExpr, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<IntValue<'gen>> {
+    let ty = expression.ty.clone();
+    match generate_value(expression, scope)? {
+        BasicValueEnum::IntValue(value) => Ok(value),
+        _ => Err(CodegenError::UnsupportedType(ty)),
     }
 }
 
-#[derive(derive_more::From)]
-struct StringConstant<'i> {
-    pair: Pair<'i,Rule>
-}
-impl <'i> StringConstant<'i> {
-    fn generate<'gen>(self, scope: &FunctionGenerator<'gen,'_,'_>) -> Result<BasicMetadataValueEnum<'gen>> {
-        let pair = self.pair.unique_inner().context("access string_value")?;
-        let raw_string = pair.as_str();
-        let value = Self::decode_user_string(raw_string);
-        let context = scope.parent.parent.context;
-        let string_value = context.const_string(value.as_bytes(), true);
-        let string_pointer = scope.builder.build_alloca(string_value.get_type(), "str_val");
-        scope.builder.build_store(string_pointer, string_value);
-        let i8_type = context.i8_type();
-        let indices = [i8_type.const_zero(), i8_type.const_zero()];
-        unsafe {
-            let pointer_value = scope.builder.build_in_bounds_gep(string_pointer, &indices, "tmp");
-            Ok(BasicMetadataValueEnum::from(pointer_value.as_basic_value_enum()))
-        }
+fn generate_literal<'gen>(literal: Literal, scope: &FunctionGenerator<'gen,'_,'_>) -> BasicValueEnum<'gen> {
+    match literal {
+        Literal::Str(value) => {
+            // string constants are globals, so they outlive the stack frame of the function that returns them
+            let global = scope.builder.build_global_string_ptr(value.as_str(), "str_val");
+            global.as_pointer_value().as_basic_value_enum()
+        },
+        Literal::Int(value) => {
+            let i64_type = scope.parent.parent.context.i64_type();
+            i64_type.const_int(value as u64, true).as_basic_value_enum()
+        },
+        Literal::Bool(value) => {
+            let bool_type = scope.parent.parent.context.bool_type();
+            bool_type.const_int(value as u64, false).as_basic_value_enum()
+        },
     }
+}
+
+fn resolve_variable<'gen>(name: &str, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let pointer = scope.resolve_symbol(name)?;
+    Ok(scope.builder.build_load(pointer, name))
+}
 
-    fn decode_user_string(raw_string: &str) -> String {
-        raw_string.to_string()
-            .replace("\\\"", "\"")
-            .replace("\\\\", "\\")
-            .replace("\\n", "\n")
-            .replace("\\r", "\r")
-            .replace("\\t", "\t")
-            .replace("\\0", "\0")
+fn generate_unary_operation<'gen>(op: UnaryOp, operand: IntValue<'gen>, scope: &FunctionGenerator<'gen,'_,'_>) -> BasicValueEnum<'gen> {
+    match op {
+        UnaryOp::Negate => scope.builder.build_int_neg(operand, "neg").as_basic_value_enum(),
+        UnaryOp::Not => scope.builder.build_not(operand, "not").as_basic_value_enum(),
     }
-}
\ No newline at end of file
+}
+
+fn generate_binary_operation<'gen>(lhs: IntValue<'gen>, op: BinaryOp, rhs: IntValue<'gen>, scope: &FunctionGenerator<'gen,'_,'_>) -> BasicValueEnum<'gen> {
+    let builder = &scope.builder;
+    let value = match op {
+        BinaryOp::Add => builder.build_int_add(lhs, rhs, "add"),
+        BinaryOp::Subtract => builder.build_int_sub(lhs, rhs, "sub"),
+        BinaryOp::Multiply => builder.build_int_mul(lhs, rhs, "mul"),
+        BinaryOp::Divide => {
+            guard_division(lhs, rhs, scope);
+            builder.build_int_signed_div(lhs, rhs, "div")
+        },
+        BinaryOp::Modulo => {
+            guard_division(lhs, rhs, scope);
+            builder.build_int_signed_rem(lhs, rhs, "rem")
+        },
+        BinaryOp::Equal => builder.build_int_compare(IntPredicate::EQ, lhs, rhs, "eq"),
+        BinaryOp::NotEqual => builder.build_int_compare(IntPredicate::NE, lhs, rhs, "ne"),
+        BinaryOp::Less => builder.build_int_compare(IntPredicate::SLT, lhs, rhs, "lt"),
+        BinaryOp::LessEqual => builder.build_int_compare(IntPredicate::SLE, lhs, rhs, "le"),
+        BinaryOp::Greater => builder.build_int_compare(IntPredicate::SGT, lhs, rhs, "gt"),
+        BinaryOp::GreaterEqual => builder.build_int_compare(IntPredicate::SGE, lhs, rhs, "ge"),
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    };
+    value.as_basic_value_enum()
+}
+
+/// Division by zero and the overflowing `i64::MIN / -1` are undefined in llvm, the program traps on both instead.
+fn guard_division<'gen>(lhs: IntValue<'gen>, rhs: IntValue<'gen>, scope: &FunctionGenerator<'gen,'_,'_>) {
+    let context = scope.parent.parent.context;
+    let builder = &scope.builder;
+    let int_type = lhs.get_type();
+    let by_zero = builder.build_int_compare(IntPredicate::EQ, rhs, int_type.const_zero(), "by_zero");
+    let smallest = builder.build_int_compare(IntPredicate::EQ, lhs, int_type.const_int(i64::MIN as u64, true), "smallest");
+    let minus_one = builder.build_int_compare(IntPredicate::EQ, rhs, int_type.const_all_ones(), "minus_one");
+    let overflow = builder.build_and(smallest, minus_one, "overflow");
+    let invalid = builder.build_or(by_zero, overflow, "invalid");
+
+    let function = builder.get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("division outside of a function");
+    let trap_bb = context.append_basic_block(function, "div_trap");
+    let valid_bb = context.append_basic_block(function, "div_valid");
+    builder.build_conditional_branch(invalid, trap_bb, valid_bb);
+    builder.position_at_end(trap_bb);
+    builder.build_call(trap(scope), &[], "");
+    builder.build_unreachable();
+    builder.position_at_end(valid_bb);
+}
+
+fn trap<'gen>(scope: &FunctionGenerator<'gen,'_,'_>) -> FunctionValue<'gen> {
+    let module = &scope.parent.module;
+    module.get_function(TRAP_FN_NAME).unwrap_or_else(|| {
+        let fn_type = scope.parent.parent.context.void_type().fn_type(&[], false);
+        module.add_function(TRAP_FN_NAME, fn_type, None)
+    })
+}
@@ -1,50 +1,81 @@
 use std::cell::{RefCell};
 use inkwell::values::{BasicValueEnum, FunctionValue, PointerValue};
-use crate::ll_code_gen::assignment::Assignment;
-use anyhow::Context as AnyhowContext;
+use inkwell::types::BasicTypeEnum;
+use crate::ll_code_gen::assignment::{generate_assignment, generate_definition, generate_field_assignment};
+use crate::ll_code_gen::control_flow::{generate_for, generate_while, LoopTargets};
+use crate::typeck::{TypedExpr, TypedParam, TypedStmt, TypedStmtKind};
 use inkwell::basic_block::BasicBlock;
-use crate::ll_code_gen::expression::generate_expression;
+use inkwell::values::BasicMetadataValueEnum;
+use crate::ll_code_gen::expression::{generate_expression, generate_value};
 use super::*;
 
 pub struct FunctionGenerator<'gen: 'module, 'module: 'func, 'func> {
     pub parent: &'func ModuleGenerator<'gen, 'module>,
-    pub symbols: RefCell<HashMap<String, PointerValue<'gen>>>,
+    /// Storage of the variables in scope, one map per block with the innermost block last.
+    symbols: RefCell<Vec<HashMap<String, PointerValue<'gen>>>>,
     pub builder: Builder<'gen>,
     function: FunctionValue<'gen>,
-    _entry: BasicBlock<'gen>,
-    last_value: RefCell<Option<BasicValueEnum<'gen>>>,
+    entry: BasicBlock<'gen>,
+    /// Jump targets of the loops around the generated code, the innermost loop last.
+    loops: RefCell<Vec<LoopTargets<'gen>>>,
+    /// The program entry point returns an `i32` exit status instead of curry values.
+    exit_status: bool,
+    /// Variables of repl inputs are globals, so later inputs can still use them.
+    globals: bool,
 }
 
 impl <'gen: 'module, 'module: 'func, 'func> FunctionGenerator<'gen, 'module, 'func> {
 
-    pub fn generate(module_gen: &'func ModuleGenerator<'gen, 'module>, name: &str, statements: Vec<Statement>) {
-        let function = FunctionGenerator::declare_void_fn(module_gen, name);
-        let fn_gen = FunctionGenerator::create_generator(module_gen, function);
+    pub fn complete(self) -> CodegenResult<()> {
+        if !self.is_terminated() {
+            self.build_return_value(None)?;
+        }
+
+        if !self.function.verify(true) {
+            return Err(CodegenError::InvalidFunction(self.function.get_name().to_string_lossy().to_string()));
+        }
+        Ok(())
+    }
 
-        for statement in statements {
-            fn_gen.add_statement(statement);
+    pub fn build_return_value(&self, value: Option<BasicValueEnum<'gen>>) -> CodegenResult<()> {
+        if self.exit_status {
+            let i32_type = self.parent.parent.context.i32_type();
+            let status = match value {
+                Some(BasicValueEnum::IntValue(value)) => self.builder.build_int_truncate(value, i32_type, "status"),
+                Some(_) => return Err(CodegenError::InvalidExitStatus),
+                None => i32_type.const_zero(),
+            };
+            self.builder.build_return(Some(&status));
+            return Ok(());
         }
 
-        fn_gen.complete();
+        match value {
+            Some(value) => self.builder.build_return(Some(&value)),
+            None => self.builder.build_return(None),
+        };
+        Ok(())
     }
 
-    pub fn complete(self) {
-        let last_value: Option<BasicValueEnum<'gen>> = self.last_value.into_inner();
-        // self.builder.build_return(last_value.as_ref()); // <- does not work
+    pub fn is_terminated(&self) -> bool {
+        self.builder.get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
 
-        match last_value {
-            Some(value) => {
-                self.builder.build_return(Some(&value));
-            },
-            None => { self.builder.build_return(None); },
+    pub fn create_main_generator(module: &'func ModuleGenerator<'gen, 'module>) -> FunctionGenerator<'gen, 'module, 'func> {
+        let fn_type = module.parent.context.i32_type().fn_type(&[], false);
+        let function = module.module.add_function(MAIN_FN_NAME, fn_type, None);
+        FunctionGenerator {
+            exit_status: true,
+            ..FunctionGenerator::create_generator(module, function)
         }
-
-        self.function.verify(true);
     }
 
-    pub fn declare_void_fn(context: &'func ModuleGenerator<'gen, 'module>, name: &str) -> FunctionValue<'gen> {
-        let fn_type = context.parent.context.void_type().fn_type(&[], false);
-        context.module.add_function(name, fn_type, None)
+    pub fn create_repl_generator(module: &'func ModuleGenerator<'gen, 'module>, function: FunctionValue<'gen>) -> FunctionGenerator<'gen, 'module, 'func> {
+        FunctionGenerator {
+            globals: true,
+            ..FunctionGenerator::create_generator(module, function)
+        }
     }
 
     pub fn create_generator(module: &'func ModuleGenerator<'gen, 'module>, function: FunctionValue<'gen>) -> FunctionGenerator<'gen, 'module, 'func> {
@@ -57,50 +88,190 @@ impl <'gen: 'module, 'module: 'func, 'func> FunctionGenerator<'gen, 'module, 'fu
             parent: module,
             function,
             builder,
-            _entry: entry,
-            symbols: RefCell::new(HashMap::new()),
-            last_value: RefCell::new(None),
+            entry,
+            symbols: RefCell::new(vec![HashMap::new()]),
+            loops: RefCell::new(Vec::new()),
+            exit_status: false,
+            globals: false,
+        }
+    }
+
+    pub fn bind_parameters(&self, params: &[TypedParam]) {
+        self.bind_values(self.function.get_param_iter(), params.iter().map(|param| param.name.clone()));
+    }
+
+    /// Stores each value in a new variable of the function.
+    pub fn bind_values(&self, values: impl IntoIterator<Item = BasicValueEnum<'gen>>, names: impl IntoIterator<Item = String>) {
+        for (value, name) in values.into_iter().zip(names) {
+            let pointer = self.build_entry_alloca(value.get_type(), name.as_str());
+            self.builder.build_store(pointer, value);
+            self.define_symbol(name, pointer);
         }
     }
 
-    pub fn add_statement(&self, statement: Statement) {
+    /// Makes the variable visible until the end of the current block, shadowing variables of outer blocks.
+    pub fn define_symbol(&self, name: String, pointer: PointerValue<'gen>) {
+        self.symbols.borrow_mut()
+            .last_mut()
+            .expect("function has a scope")
+            .insert(name, pointer);
+    }
+
+    /// The storage of the variable, looked up from the innermost block outwards.
+    pub fn resolve_symbol(&self, name: &str) -> CodegenResult<PointerValue<'gen>> {
+        self.symbols.borrow().iter().rev()
+            .find_map(|scope| scope.get(name).copied())
+            .ok_or_else(|| CodegenError::UnknownVariable(name.to_string()))
+    }
+
+    /// Generates a block, the variables it defines are dropped from the symbols afterwards.
+    pub fn in_scope<T>(&self, generate: impl FnOnce() -> CodegenResult<T>) -> CodegenResult<T> {
+        self.symbols.borrow_mut().push(HashMap::new());
+        let result = generate();
+        self.symbols.borrow_mut().pop();
+        result
+    }
+
+    pub fn append_block(&self, name: &str) -> BasicBlock<'gen> {
+        self.parent.parent.context.append_basic_block(self.function, name)
+    }
+
+    /// The block instructions are currently added to.
+    pub fn current_block(&self) -> BasicBlock<'gen> {
+        self.builder.get_insert_block().expect("builder is positioned in a block")
+    }
+
+    /// Stack slots are allocated at the top of the entry block, so each variable gets exactly one per call.
+    pub fn build_entry_alloca(&self, ty: BasicTypeEnum<'gen>, name: &str) -> PointerValue<'gen> {
+        let builder = self.parent.parent.context.create_builder();
+        match self.entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(self.entry),
+        }
+        builder.build_alloca(ty, name)
+    }
+
+    /// Storage for a new variable, a stack slot or a global for repl inputs.
+    pub fn build_variable(&self, ty: BasicTypeEnum<'gen>, name: &str) -> PointerValue<'gen> {
+        if !self.globals {
+            return self.build_entry_alloca(ty, name);
+        }
+        // the module name keeps the symbol unique when a later input defines the same variable again
+        let module = &self.parent.module;
+        let symbol = format!("{}.{}", name, module.get_name().to_string_lossy());
+        let global = module.add_global(ty, None, symbol.as_str());
+        global.set_initializer(&ty.const_zero());
+        global.as_pointer_value()
+    }
+
+    pub fn generate_statements(&self, statements: Vec<TypedStmt>) -> CodegenResult<()> {
+        statements.into_iter()
+            .try_for_each(|statement| self.generate_statement(statement))
+    }
+
+    fn generate_statement(&self, statement: TypedStmt) -> CodegenResult<()> {
         match statement.kind {
-            StatementKind::Assignment(pair) => {
-                let assignment : Assignment = pair.try_into().context("resolve assignment").unwrap();
-                let value_enum = assignment.process(self).context("function block -> statement").unwrap();
-                self.last_value.replace(Some(value_enum));
+            TypedStmtKind::Define { name, value, .. } => {
+                generate_definition(name, value, self)?;
             },
-            StatementKind::FunctionCall(call) => {
-                self.process_function_call(call);
-            }
-        }
+            TypedStmtKind::Assign { name, value } => {
+                generate_assignment(name, value, self)?;
+            },
+            TypedStmtKind::AssignField { name, path, value } => {
+                generate_field_assignment(name, path, value, self)?;
+            },
+            TypedStmtKind::Expression(expression) => {
+                generate_expression(expression, self)?;
+            },
+            TypedStmtKind::Return(expression) => {
+                let value = expression.map(|it| generate_expression(it, self))
+                    .transpose()?
+                    .flatten();
+                self.build_return_value(value)?;
+            },
+            TypedStmtKind::While { condition, body } => generate_while(condition, body, self)?,
+            TypedStmtKind::For { variable, start, end, body } => generate_for(variable, start, end, body, self)?,
+            TypedStmtKind::Block(block) => self.in_scope(|| self.generate_statements(block.statements))?,
+            TypedStmtKind::Break => {
+                let targets = self.innermost_loop()?;
+                self.builder.build_unconditional_branch(targets.break_bb);
+            },
+            TypedStmtKind::Continue => {
+                let targets = self.innermost_loop()?;
+                self.builder.build_unconditional_branch(targets.continue_bb);
+            },
+        };
+        Ok(())
+    }
+
+    /// Generates the body of a loop, `break` and `continue` inside it jump to the given targets.
+    pub fn generate_loop_body(&self, statements: Vec<TypedStmt>, targets: LoopTargets<'gen>) -> CodegenResult<()> {
+        self.loops.borrow_mut().push(targets);
+        let result = self.in_scope(|| self.generate_statements(statements));
+        self.loops.borrow_mut().pop();
+        result
     }
 
-    fn build_fn_args(&self, pairs: Pairs<Rule>) -> Result<Vec<BasicMetadataValueEnum<'gen>>> {
-        pairs.into_iter()
-            .map(|arg| generate_expression(arg, self))
-            .collect::<Result<Vec<BasicMetadataValueEnum>>>()
+    fn innermost_loop(&self) -> CodegenResult<LoopTargets<'gen>> {
+        self.loops.borrow().last().copied().ok_or(CodegenError::JumpOutsideOfLoop)
+    }
+
+    pub fn generate_function_call(&self, function: String, args: Vec<TypedExpr>) -> CodegenResult<Option<BasicValueEnum<'gen>>> {
+        let fixed_params = self.parent.functions.borrow().get(function.as_str())
+            .map_or(0, |function| function.count_params() as usize);
+        let args = args.into_iter()
+            .enumerate()
+            .map(|(position, arg)| {
+                let value = generate_value(arg, self)?;
+                let value = if position >= fixed_params { self.promote_variadic_argument(value) } else { value };
+                Ok(value)
+            })
+            .collect::<CodegenResult<Vec<BasicValueEnum>>>()?;
+
+        self.create_function_call(function.as_str(), args)
     }
 
+    /// Like c, variadic arguments narrower than `int` are passed as `int`, e.g. bools for `printf`.
+    fn promote_variadic_argument(&self, value: BasicValueEnum<'gen>) -> BasicValueEnum<'gen> {
+        match value {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() < 32 => {
+                let i32_type = self.parent.parent.context.i32_type();
+                self.builder.build_int_z_extend(value, i32_type, "promoted").into()
+            },
+            value => value,
+        }
+    }
 
-    pub fn process_function_call(&self, call: Pair<Rule>) -> Option<BasicValueEnum<'gen>> {
-        let mut inner = call.into_inner();
-        let symbol_ref = inner.next().expect("function call requires symbol ref").as_str();
-        let fn_args = inner.next().expect("function call requires arguments");
-        let args = self.build_fn_args(fn_args.into_inner())
-            .context("resolve function arguments")
-            .unwrap();
+    pub fn create_function_call(&self, symbol_ref: &str, args: Vec<BasicValueEnum<'gen>>) -> CodegenResult<Option<BasicValueEnum<'gen>>> {
+        let function = *self.parent.functions.borrow().get(symbol_ref)
+            .ok_or_else(|| CodegenError::UndefinedFunction(symbol_ref.to_string()))?;
 
-        self.create_function_call(symbol_ref, args)
+        Ok(self.build_direct_call(function, &args, symbol_ref))
     }
 
-    pub fn create_function_call(&self, symbol_ref: &str, args: Vec<BasicMetadataValueEnum<'gen>>) -> Option<BasicValueEnum<'gen>> {
-        let value = self.builder.build_call(
-            self.parent.module.get_function(symbol_ref).unwrap_or_else(|| panic!("{} not defined", symbol_ref)),
-            args.as_ref(),
-            symbol_ref
-        );
-        value.try_as_basic_value().left()
+    /// Calls a declared function. Externs may take or return c ints, the `int` arguments are truncated to their
+    /// width and the result is sign extended back to an `int`.
+    pub fn build_direct_call(&self, function: FunctionValue<'gen>, args: &[BasicValueEnum<'gen>], name: &str) -> Option<BasicValueEnum<'gen>> {
+        let param_types = function.get_type().get_param_types();
+        let args = args.iter()
+            .enumerate()
+            .map(|(position, arg)| match (arg, param_types.get(position)) {
+                (BasicValueEnum::IntValue(value), Some(BasicTypeEnum::IntType(param_type)))
+                    if value.get_type().get_bit_width() > param_type.get_bit_width() => {
+                    self.builder.build_int_truncate(*value, *param_type, "cint").into()
+                },
+                (arg, _) => BasicMetadataValueEnum::from(*arg),
+            })
+            .collect::<Vec<_>>();
+
+        let value = self.builder.build_call(function, &args, name).try_as_basic_value().left();
+        match value {
+            Some(BasicValueEnum::IntValue(value)) if value.get_type().get_bit_width() == 32 => {
+                let int_type = self.parent.parent.context.i64_type();
+                Some(self.builder.build_int_s_extend(value, int_type, "int").into())
+            },
+            value => value,
+        }
     }
 }
 
@@ -120,6 +291,7 @@ pub mod libc {
         let param_types = &[char_array.into()];
         let void = module_gen.parent.context.void_type();
         let type_printf = void.fn_type(param_types, true);
-        module_gen.module.add_function("printf", type_printf, None);
+        let printf = module_gen.module.add_function("printf", type_printf, None);
+        module_gen.functions.borrow_mut().insert("printf".to_string(), printf);
     }
 }
@@ -0,0 +1,77 @@
+//! Running the compiled program in-process, without writing it to disk.
+
+use std::os::raw::{c_int, c_void};
+use super::{CompileError, LLIRCodeGenerator, MAIN_FN_NAME};
+
+type MainFn = unsafe extern "C" fn() -> c_int;
+
+extern "C" {
+    fn fflush(stream: *mut c_void) -> c_int;
+}
+
+impl <'gen> LLIRCodeGenerator<'gen> {
+    /// Compiles the source and runs it with the jit, returning the exit status of `main`.
+    pub fn run_jit(&mut self, input: String) -> Result<i32, CompileError> {
+        self.compile_source(input)?;
+        self.execute_main()
+    }
+
+    /// Executes `main` of the compiled program. Builtins like `printf` resolve to the symbols of the host process.
+    pub fn execute_main(&self) -> Result<i32, CompileError> {
+        // the execution engine takes ownership of its module, a copy keeps the compiled one usable afterwards
+        let module = self.main_module()
+            .ok_or_else(|| CompileError::Jit("no program has been compiled".to_string()))?
+            .clone();
+        let engine = module.create_jit_execution_engine(self.opt_level.codegen_level())
+            .map_err(|error| CompileError::Jit(error.to_string()))?;
+
+        let status = unsafe {
+            let main = engine.get_function::<MainFn>(MAIN_FN_NAME)
+                .map_err(|error| CompileError::Jit(error.to_string()))?;
+            main.call()
+        };
+        flush_c_stdio();
+        Ok(status)
+    }
+}
+
+/// Jitted code writes through the buffered c stdio of this process, which has to be flushed to show its output.
+pub(crate) fn flush_c_stdio() {
+    unsafe {
+        fflush(std::ptr::null_mut());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use inkwell::context::Context;
+    use super::*;
+
+    #[test]
+    fn it_returns_the_exit_status() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+        let source = "entry: main\n\nfunc answer() {\n\t40 + 2\n}\n\nfunc main() {\n\tprintf(\"jit\\n\")\n\treturn answer()\n}\n";
+
+        assert_eq!(42, code_gen.run_jit(source.to_string()).unwrap());
+    }
+
+    #[test]
+    fn compiled_program_can_run_repeatedly() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+        code_gen.compile_source("x = 7\nx * 3".to_string()).unwrap();
+
+        assert_eq!(0, code_gen.execute_main().unwrap());
+        assert_eq!(0, code_gen.execute_main().unwrap());
+        assert!(code_gen.main_module().unwrap().print_to_string().to_string().contains("define i32 @main()"));
+    }
+
+    #[test]
+    fn source_errors_are_not_executed() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+
+        assert!(matches!(code_gen.run_jit("x = 1 +".to_string()), Err(CompileError::Source(_))));
+    }
+}
@@ -1,23 +1,35 @@
 use std::collections::HashMap;
-use anyhow::*;
-use anyhow::Context as AnyhowContext;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::values::{BasicMetadataValueEnum};
-use pest::iterators::{Pairs};
-use std::convert::TryInto;
+use inkwell::module::{Linkage, Module};
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::types::StructType;
+use inkwell::values::{BasicValueEnum, FunctionValue};
 use std::fs::read_to_string;
 use std::path::Path;
 
+use std::cell::RefCell;
 use std::rc::Rc;
 use crate::ll_code_gen::function_generator::FunctionGenerator;
 use crate::ll_code_gen::function_generator::libc::declare_libc_builtin;
-use crate::parser::ast::*;
+use crate::diagnostics::{Diagnostic, SourceError, SourceFile};
+use crate::parser::parse_to_ast;
+use crate::typeck::{check_source, Entry, ExternType, Type, TypedExtern, TypedFunction, TypedProgram};
+pub use crate::ll_code_gen::errors::{CodegenError, CodegenResult, CompileError};
+pub use crate::ll_code_gen::optimize::OptLevel;
 
+pub mod errors;
 mod function_generator;
 mod assignment;
 mod expression;
+mod control_flow;
+mod closure;
+mod structs;
+mod types;
+mod native;
+pub(crate) mod jit;
+mod repl;
+mod optimize;
 
 const ENTRY_BLOCK_NAME: &str = "entry";
 const MAIN_FN_NAME: &str = "main";
@@ -25,7 +37,8 @@ const MAIN_FN_NAME: &str = "main";
 #[derive(Debug)]
 pub struct LLIRCodeGenerator<'gen> {
     pub context: &'gen Context,
-    pub modules: HashMap<String, Rc<Module<'gen>>>
+    pub modules: HashMap<String, Rc<Module<'gen>>>,
+    pub opt_level: OptLevel,
 }
 
 impl <'gen> LLIRCodeGenerator<'gen> {
@@ -33,76 +46,59 @@ impl <'gen> LLIRCodeGenerator<'gen> {
         LLIRCodeGenerator {
             context,
             modules: HashMap::new(),
+            opt_level: OptLevel::default(),
         }
     }
 
-    pub fn  compile_source_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let input = read_to_string(&path)
-            .with_context(|| format!("reading {}", path.as_ref().display()))?;
-
-        self.compile_source(input).context("compile source from file")
+    pub fn with_opt_level(self, opt_level: OptLevel) -> Self {
+        LLIRCodeGenerator { opt_level, ..self }
     }
 
-    pub fn compile_source(&mut self, input: String) -> Result<()> {
-
-        let source = parse_to_ast(input.as_ref()).context("compile source")?;
+    pub fn  compile_source_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CompileError> {
+        self.compile(read_source_file(path)?)
+    }
 
-        match source.as_rule() {
-            Rule::script => {
-                let statements: Result<Vec<Statement>> = source.into_inner()
-                    .filter(|pair| pair.as_rule() != Rule::EOI)
-                    .map(|pair| -> Result<Statement,Error> {
-                        pair.try_into().context("couldn't parse stmt")
-                    })
-                    .collect();
+    pub fn compile_source(&mut self, input: String) -> Result<(), CompileError> {
+        self.compile(SourceFile::new("<source>", input))
+    }
 
-                self.create_script(statements?)
-                    .context("create script")?
-            },
-            Rule::program => self.create_program(source).context("create program")?,
-            it => unreachable!("unexpected rule {}", it)
-        };
+    /// Errors in the source are returned as [`SourceError`], which renders all diagnostics when displayed.
+    fn compile(&mut self, file: SourceFile) -> Result<(), CompileError> {
+        let program = analyze(file)?;
+        Ok(self.create_program(program)?)
+    }
 
-        Ok(())
+    /// The module holding the program entry point, once a program has been compiled.
+    pub fn main_module(&self) -> Option<&Module<'gen>> {
+        self.modules.get(MAIN_FN_NAME).map(Rc::as_ref)
     }
 
-    fn create_program(&mut self, program: Pair<Rule>) -> Result<()> {
-        let mut entry: Option<&str> = None;
+    fn create_program(&mut self, program: TypedProgram) -> CodegenResult<()> {
         let module_gen = self.create_module_generator(MAIN_FN_NAME);
 
         module_gen.declare_libc_builtin();
+        let main_gen = FunctionGenerator::create_main_generator(&module_gen);
 
-        let pairs = program.into_inner();
-        for pair in pairs {
-            match pair.as_rule() {
-                Rule::entry_definition => {
-                    match entry {
-                        Some(_) => Err(Error::msg("encountered multiple entry definitions"))?,
-                        None => entry = Some(pair.unique_inner()?.as_str()),
-                    }
-                },
-                Rule::function_declaration => {
-                    module_gen.process_function_declaration(pair)?
-                },
-                Rule::EOI => {},
-                it => unreachable!("unexpected rule {}", it),
-            }
+        module_gen.declare_structs(&program.structs)?;
+        for external in program.externs.iter() {
+            module_gen.declare_extern(external)?;
         }
-
-        match entry {
-            Some(entry_fn_name) => module_gen.create_program_main(entry_fn_name),
-            None => unreachable!("entry definition missing"),
+        for function in program.functions.iter() {
+            module_gen.declare_function(function)?;
+        }
+        for function in program.functions {
+            module_gen.generate_function(function)?;
         }
 
-        Ok(())
-    }
-
-    fn create_script(&mut self, statements: Vec<Statement>) -> Result<()> {
-        let name = MAIN_FN_NAME;
-        let module_gen = self.create_module_generator(name);
-        module_gen.declare_libc_builtin();
-        module_gen.generate_function_from_statements(MAIN_FN_NAME, statements);
-
+        match program.entry {
+            Entry::Script(statements) => {
+                main_gen.generate_statements(statements)
+                    .and_then(|_| main_gen.complete())
+                    .map_err(|error| error.in_function(MAIN_FN_NAME))?
+            },
+            Entry::Function(entry_fn_name) => module_gen.create_program_main(main_gen, &entry_fn_name)?,
+        }
+        module_gen.optimize();
         Ok(())
     }
 
@@ -116,57 +112,170 @@ impl <'gen> LLIRCodeGenerator<'gen> {
     }
 }
 
+/// Parses and type checks a source file without generating any code.
+pub fn check_source_file<P: AsRef<Path>>(path: P) -> Result<(), CompileError> {
+    analyze(read_source_file(path)?)?;
+    Ok(())
+}
+
+fn read_source_file<P: AsRef<Path>>(path: P) -> Result<SourceFile, CompileError> {
+    let path = path.as_ref();
+    let input = read_to_string(path)
+        .map_err(|source| CompileError::Io { path: path.to_path_buf(), source })?;
+    Ok(SourceFile::new(path.display().to_string(), input))
+}
+
+fn analyze(file: SourceFile) -> Result<TypedProgram, SourceError> {
+    let program = parse_to_ast(&file.text)
+        .map_err(|error| vec![Diagnostic::from(error)])
+        .and_then(|source| check_source(source, &file.text)
+            .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect()));
+
+    program.map_err(|diagnostics| SourceError { file, diagnostics })
+}
 
 #[derive(Debug)]
 pub struct ModuleGenerator<'gen: 'module, 'module> {
     pub parent: &'module LLIRCodeGenerator<'gen>,
     pub module: Rc<Module<'gen>>,
-    pub builder: Builder<'gen>,
+    /// Functions by their curry name, which may differ from the llvm symbol, e.g. for a user defined `main`
+    pub functions: RefCell<HashMap<String, FunctionValue<'gen>>>,
+    /// Struct types by their curry name, llvm renames a type if its name is already taken in the context
+    pub structs: RefCell<HashMap<String, StructType<'gen>>>,
 }
 
 impl <'gen: 'module, 'module> ModuleGenerator<'gen, 'module> {
 
     pub fn create(parent: &'module LLIRCodeGenerator<'gen>, module: Rc<Module<'gen>>) -> Self {
-        let builder = parent.context.create_builder();
-        ModuleGenerator {module, parent, builder}
+        ModuleGenerator {module, parent, functions: RefCell::new(HashMap::new()), structs: RefCell::new(HashMap::new())}
     }
 
     pub fn declare_libc_builtin(&self) {
         declare_libc_builtin(self);
     }
 
-    pub fn create_program_main(&self, entry_fn_name: &str) {
-        let function = FunctionGenerator::declare_void_fn(self, MAIN_FN_NAME);
-        let fn_gen = FunctionGenerator::create_generator(self, function);
-        fn_gen.create_function_call(entry_fn_name, Vec::new());
-        fn_gen.complete();
+    /// Runs the passes of the selected optimization level over the finished module.
+    pub fn optimize(&self) {
+        self.parent.opt_level.optimize(&self.module);
     }
 
-    pub fn generate_function_from_statements(&self, name: &str, statements: Vec<Statement>) {
-        FunctionGenerator::generate(self, name, statements);
+    /// Generates the `main` entry point calling the entry function. An integer result becomes the exit status.
+    pub fn create_program_main(&self, fn_gen: FunctionGenerator<'gen, 'module, '_>, entry_fn_name: &str) -> CodegenResult<()> {
+        let value = fn_gen.create_function_call(entry_fn_name, Vec::new())
+            .map_err(|error| error.in_function(MAIN_FN_NAME))?;
+        if let Some(status @ BasicValueEnum::IntValue(_)) = value {
+            fn_gen.build_return_value(Some(status))?;
+        }
+        fn_gen.complete()
     }
-    pub fn process_function_declaration(&self, pair: Pair<Rule>) -> Result<()> {
-        let _line_col = pair.line_col();
-        let mut pairs = pair.into_inner();
-        let name = pairs.next().context("function name")?.as_str();
-        let _args = pairs.next().context("function args")?.into_inner();
-        let body = pairs.next()
-            .context("function body")?
-            .into_inner();
 
-        let function = FunctionGenerator::declare_void_fn(self, name);
-        let fn_gen = FunctionGenerator::create_generator(self, function);
+    /// Declares a foreign function, the linker or jit resolves it by its name. Like c, bools and c ints are extended
+    /// to the width of a register by whoever passes them.
+    pub fn declare_extern(&self, external: &TypedExtern) -> CodegenResult<()> {
+        let fn_type = self.extern_fn_type(external)
+            .map_err(|error| error.in_function(&external.name))?;
+        let function_value = self.module.add_function(external.name.as_str(), fn_type, Some(Linkage::External));
+        let locations = external.params.iter()
+            .enumerate()
+            .map(|(position, param)| (AttributeLoc::Param(position as u32), param))
+            .chain(std::iter::once((AttributeLoc::Return, &external.return_type)));
+        for (location, ty) in locations {
+            let extension = match ty {
+                ExternType::Value(Type::Bool) => "zeroext",
+                ExternType::CInt => "signext",
+                _ => continue,
+            };
+            let kind = Attribute::get_named_enum_kind_id(extension);
+            function_value.add_attribute(location, self.parent.context.create_enum_attribute(kind, 0));
+        }
+        self.functions.borrow_mut().insert(external.name.clone(), function_value);
+
+        Ok(())
+    }
 
-        body.map( |it| it.try_into().context("function body"))
-            .try_for_each(|it|
-                it.map(|pair|
-                    fn_gen.add_statement(pair)
-                )
-            )?;
+    pub fn declare_function(&self, function: &TypedFunction) -> CodegenResult<()> {
+        let param_types = function.params.iter()
+            .map(|param| param.ty.clone())
+            .collect::<Vec<_>>();
+        let fn_type = self.llvm_fn_type(&param_types, false, &function.return_type)
+            .map_err(|error| error.in_function(&function.name))?;
 
-        fn_gen.complete();
+        let function_value = self.module.add_function(function.name.as_str(), fn_type, None);
+        for (value, param) in function_value.get_param_iter().zip(function.params.iter()) {
+            value.set_name(param.name.as_str());
+        }
+        self.functions.borrow_mut().insert(function.name.clone(), function_value);
 
         Ok(())
     }
 
+    pub fn generate_function(&self, function: TypedFunction) -> CodegenResult<()> {
+        let function_value = *self.functions.borrow().get(function.name.as_str())
+            .ok_or_else(|| CodegenError::UndefinedFunction(function.name.clone()))?;
+        let fn_gen = FunctionGenerator::create_generator(self, function_value);
+        fn_gen.bind_parameters(&function.params);
+
+        let name = function.name;
+        fn_gen.generate_statements(function.body)
+            .and_then(|_| fn_gen.complete())
+            .map_err(|error| error.in_function(&name))
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Span;
+    use crate::typeck::{Type, TypedExpr, TypedExprKind, TypedStmt, TypedStmtKind};
+
+    #[test]
+    fn source_errors_are_returned() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+
+        for input in ["printf(name)", "entry: run\n\nfunc main() {\n}\n", "greet(\"Chuck\")", "x = 1 +"] {
+            match code_gen.compile_source(input.to_string()) {
+                Err(CompileError::Source(_)) => {},
+                other => panic!("unexpected result {:?} for {}", other, input),
+            }
+        }
+    }
+
+    #[test]
+    fn structs_keep_their_type_when_the_name_is_taken() {
+        let context = Context::create();
+        context.opaque_struct_type("Point");
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+
+        let input = "entry: main\n\nstruct Point { x: int }\n\nfunc main() -> int {\n\tlet p = Point { x: 1 }\n\tp.x\n}\n";
+        code_gen.compile_source(input.to_string()).unwrap();
+        let module = code_gen.main_module().unwrap();
+        assert!(module.verify().is_ok());
+        assert!(module.print_to_string().to_string().contains("%Point.0 = type { i64 }"));
+    }
+
+    #[test]
+    fn undefined_function_is_codegen_error() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+        let call = TypedExpr {
+            kind: TypedExprKind::Call { function: "missing".to_string(), args: Vec::new() },
+            ty: Type::Unit,
+            span: Span::default(),
+        };
+        let program = TypedProgram {
+            externs: Vec::new(),
+            structs: Vec::new(),
+            functions: Vec::new(),
+            entry: Entry::Script(vec![TypedStmt { kind: TypedStmtKind::Expression(call), span: Span::default() }]),
+        };
+
+        match code_gen.create_program(program) {
+            Err(CodegenError::InFunction { source, .. }) => {
+                assert!(matches!(*source, CodegenError::UndefinedFunction(ref name) if name == "missing"))
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,165 @@
+//! Emitting the compiled program as native object code or assembly for the host, and linking executables.
+//!
+//! Object and assembly files are written by llvm alone. Linking an executable still needs a c compiler driver,
+//! `cc` or the one named by `CC`, which provides the linker and the c runtime.
+
+use std::ffi::OsString;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use inkwell::module::Module;
+use inkwell::OptimizationLevel;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine};
+use super::{CompileError, LLIRCodeGenerator};
+
+/// Environment variable naming the C compiler driver used to link executables, `cc` if unset.
+const LINKER_ENV: &str = "CC";
+const DEFAULT_LINKER: &str = "cc";
+
+impl <'gen> LLIRCodeGenerator<'gen> {
+    pub fn write_object_file<P: AsRef<Path>>(&self, path: P) -> Result<(), CompileError> {
+        self.write_native_file(path.as_ref(), FileType::Object)
+    }
+
+    pub fn write_assembly_file<P: AsRef<Path>>(&self, path: P) -> Result<(), CompileError> {
+        self.write_native_file(path.as_ref(), FileType::Assembly)
+    }
+
+    /// Writes an object file to a temporary directory and links it into an executable at `path`.
+    pub fn build_executable<P: AsRef<Path>>(&self, path: P) -> Result<(), CompileError> {
+        let path = path.as_ref();
+        let build_dir = create_build_dir(path)?;
+        let object = build_dir.join("program.o");
+        let linked = self.write_object_file(&object)
+            .and_then(|_| link_executable(&object, path));
+        let _ = remove_dir_all(&build_dir);
+        linked
+    }
+
+    fn write_native_file(&self, path: &Path, file_type: FileType) -> Result<(), CompileError> {
+        let module = self.main_module()
+            .ok_or_else(|| CompileError::Target("no program has been compiled".to_string()))?;
+        let machine = host_target_machine(self.opt_level.codegen_level())?;
+        prepare_module(module, &machine);
+
+        machine.write_to_file(module, file_type, path)
+            .map_err(|error| CompileError::Target(format!("writing {}: {}", path.display(), error)))
+    }
+}
+
+/// A directory of its own for the intermediate files of one build.
+fn create_build_dir(output: &Path) -> Result<PathBuf, CompileError> {
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+    let build_dir = std::env::temp_dir().join(format!("curry-build-{}-{}", std::process::id(), build));
+    create_dir_all(&build_dir)
+        .map_err(|error| CompileError::Link {
+            path: output.to_path_buf(),
+            reason: format!("creating {}: {}", build_dir.display(), error),
+        })?;
+    Ok(build_dir)
+}
+
+/// Links an object file with the c runtime, which provides `printf` and the process entry point calling `main`.
+fn link_executable(object: &Path, output: &Path) -> Result<(), CompileError> {
+    let linker = std::env::var_os(LINKER_ENV).unwrap_or_else(|| OsString::from(DEFAULT_LINKER));
+    let status = Command::new(&linker)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|error| CompileError::Link {
+            path: output.to_path_buf(),
+            reason: match error.kind() {
+                ErrorKind::NotFound => format!(
+                    "no c compiler driver '{}' found, install one or name it with {}", linker.to_string_lossy(), LINKER_ENV
+                ),
+                _ => format!("invoking {}: {}", linker.to_string_lossy(), error),
+            },
+        })?;
+
+    if !status.success() {
+        return Err(CompileError::Link { path: output.to_path_buf(), reason: format!("linker exited with {}", status) });
+    }
+    Ok(())
+}
+
+fn host_target_machine(opt_level: OptimizationLevel) -> Result<TargetMachine, CompileError> {
+    Target::initialize_native(&InitializationConfig::default())
+        .map_err(CompileError::Target)?;
+
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple)
+        .map_err(|error| CompileError::Target(error.to_string()))?;
+    // position independent code, as linkers default to producing position independent executables
+    target.create_target_machine(
+        &triple,
+        &TargetMachine::get_host_cpu_name().to_string(),
+        &TargetMachine::get_host_cpu_features().to_string(),
+        opt_level,
+        RelocMode::PIC,
+        CodeModel::Default,
+    ).ok_or_else(|| CompileError::Target(format!("no target machine for {}", triple)))
+}
+
+fn prepare_module(module: &Module<'_>, machine: &TargetMachine) {
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{read, read_to_string, remove_file, write};
+    use inkwell::context::Context;
+    use super::*;
+
+    #[test]
+    fn it_writes_object_and_assembly_files() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+        code_gen.compile_source("printf(\"native\")".to_string()).unwrap();
+
+        let dir = std::env::temp_dir();
+        let object = dir.join(format!("curry-native-{}.o", std::process::id()));
+        let assembly = object.with_extension("s");
+        code_gen.write_object_file(&object).unwrap();
+        code_gen.write_assembly_file(&assembly).unwrap();
+
+        let object_code = read(&object).unwrap();
+        let assembly_code = read_to_string(&assembly).unwrap();
+        remove_file(&object).unwrap();
+        remove_file(&assembly).unwrap();
+
+        assert!(!object_code.is_empty());
+        assert!(assembly_code.contains("main"));
+    }
+
+    #[test]
+    fn executables_keep_files_next_to_them() {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context);
+        code_gen.compile_source("printf(\"native\")".to_string()).unwrap();
+
+        let executable = std::env::temp_dir().join(format!("curry-native-exe-{}", std::process::id()));
+        let object = executable.with_extension("o");
+        write(&object, "unrelated").unwrap();
+        code_gen.build_executable(&executable).unwrap();
+
+        let object_content = read_to_string(&object).unwrap();
+        remove_file(&object).unwrap();
+        remove_file(&executable).unwrap();
+
+        assert_eq!("unrelated", object_content);
+    }
+
+    #[test]
+    fn nothing_to_emit_before_compiling() {
+        let context = Context::create();
+        let code_gen = LLIRCodeGenerator::new(&context);
+        let path = std::env::temp_dir().join("curry-native-missing.o");
+
+        assert!(matches!(code_gen.write_object_file(path), Err(CompileError::Target(_))));
+    }
+}
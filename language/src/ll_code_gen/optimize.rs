@@ -0,0 +1,122 @@
+//! Optimization levels and the llvm passes they run over each generated module.
+
+use std::fmt;
+use std::str::FromStr;
+use inkwell::module::Module;
+use inkwell::OptimizationLevel;
+use inkwell::passes::PassManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// No optimizations, the ir is emitted as generated.
+    #[default]
+    O0,
+    O1,
+    O2,
+    /// Like `O2`, with more aggressive instruction combining and dead code elimination.
+    O3,
+    /// Like `O2`, but only inlines functions marked `alwaysinline` to keep the code small.
+    Os,
+}
+
+impl OptLevel {
+    /// Runs the passes of this level over the module.
+    pub fn optimize(self, module: &Module<'_>) {
+        if self == OptLevel::O0 {
+            return;
+        }
+
+        let passes = PassManager::create(());
+        passes.add_promote_memory_to_register_pass();
+        passes.add_instruction_combining_pass();
+        passes.add_cfg_simplification_pass();
+        match self {
+            OptLevel::O0 | OptLevel::O1 => {},
+            OptLevel::O2 | OptLevel::O3 => passes.add_function_inlining_pass(),
+            OptLevel::Os => passes.add_always_inliner_pass(),
+        }
+        if self != OptLevel::O1 {
+            passes.add_reassociate_pass();
+            passes.add_gvn_pass();
+        }
+        passes.add_dead_store_elimination_pass();
+        if self == OptLevel::O3 {
+            passes.add_aggressive_inst_combiner_pass();
+            passes.add_aggressive_dce_pass();
+        }
+        passes.add_cfg_simplification_pass();
+        passes.add_global_dce_pass();
+        passes.run_on(module);
+    }
+
+    /// The matching level of llvm code generators, e.g. for emitting native code.
+    pub fn codegen_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    /// Parses the level as given to `-O`, e.g. `2` or `s`.
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            other => Err(format!("unknown optimization level '{}', expected one of 0, 1, 2, 3 or s", other)),
+        }
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use inkwell::context::Context;
+    use crate::ll_code_gen::LLIRCodeGenerator;
+    use super::*;
+
+    const CALUMNI: &str = include_str!("../../tests/baseline_tests/calumni/calumni.cry");
+
+    fn generate_ir(opt_level: OptLevel, source: &str) -> String {
+        let context = Context::create();
+        let mut code_gen = LLIRCodeGenerator::new(&context).with_opt_level(opt_level);
+        code_gen.compile_source(source.to_string()).unwrap();
+        code_gen.main_module().unwrap().print_to_string().to_string()
+    }
+
+    #[test]
+    fn optimized_calumni_has_no_allocas() {
+        assert!(generate_ir(OptLevel::O0, CALUMNI).contains("alloca"));
+        for opt_level in [OptLevel::O1, OptLevel::O2, OptLevel::O3, OptLevel::Os] {
+            let ir = generate_ir(opt_level, CALUMNI);
+            assert!(!ir.contains("alloca"), "{} left allocas in\n{}", opt_level, ir);
+        }
+    }
+
+    #[test]
+    fn it_inlines_functions() {
+        let source = "entry: main\n\nfunc answer() {\n\t40 + 2\n}\n\nfunc main() {\n\treturn answer()\n}\n";
+
+        assert!(generate_ir(OptLevel::O2, source).contains("ret i32 42"));
+    }
+
+    #[test]
+    fn it_parses_levels() {
+        assert_eq!(Ok(OptLevel::O3), "3".parse());
+        assert_eq!(Ok(OptLevel::Os), "s".parse());
+        assert!("4".parse::<OptLevel>().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use inkwell::AddressSpace;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::module::Module;
+use inkwell::types::StructType as LLVMStructType;
+use inkwell::values::{BasicValueEnum, FunctionValue};
+use crate::ll_code_gen::expression::generate_expression;
+use crate::typeck::{Entry, FunctionType, Session, StructType, Type, TypedExpr, TypedProgram, TypedStmt, TypedStmtKind, Variable};
+use super::*;
+
+/// The module generated for one repl input.
+#[derive(Debug)]
+pub struct ReplModule<'gen> {
+    pub module: Rc<Module<'gen>>,
+    /// Function running the statements of the input, it returns the value of a trailing expression. Struct values are
+    /// returned as a pointer to a copy, so the shell can read their fields.
+    pub function: String,
+    pub result: Type,
+    /// The variables defined by the input and their global symbols.
+    pub variables: HashMap<String, (Variable, String)>,
+    pub functions: HashMap<String, FunctionType>,
+    pub structs: Vec<StructType>,
+    /// The llvm types of the structs of the session, including those declared by the input.
+    pub struct_types: HashMap<String, LLVMStructType<'gen>>,
+}
+
+impl <'gen> LLIRCodeGenerator<'gen> {
+    /// Generates a module for a checked repl input. Functions and variables of earlier inputs are declared by the
+    /// module and resolved against the modules of those inputs, `symbols` maps variables to their globals and
+    /// `struct_types` holds the types of the structs declared by earlier inputs.
+    pub(crate) fn create_repl_module(&mut self, name: &str, program: TypedProgram, session: &Session, symbols: &HashMap<String, String>, struct_types: &HashMap<String, LLVMStructType<'gen>>) -> CodegenResult<ReplModule<'gen>> {
+        let module_gen = self.create_module_generator(name);
+        module_gen.declare_libc_builtin();
+        module_gen.structs.borrow_mut().extend(struct_types.iter().map(|(name, struct_type)| (name.clone(), *struct_type)));
+        module_gen.declare_structs(&program.structs)?;
+        for (function_name, signature) in session.functions.iter() {
+            // externs keep the c signature of the module declaring them
+            let declaration = module_gen.parent.modules.values()
+                .find_map(|module| module.get_function(function_name));
+            let function = match declaration {
+                Some(declaration) => redeclare_function(&module_gen.module, declaration),
+                None => {
+                    let fn_type = module_gen.llvm_fn_type(&signature.params, signature.variadic, &signature.return_type)?;
+                    module_gen.module.add_function(function_name.as_str(), fn_type, None)
+                },
+            };
+            module_gen.functions.borrow_mut().insert(function_name.clone(), function);
+        }
+
+        let externs = program.externs.iter()
+            .map(|external| (external.name.clone(), external.signature()));
+        let functions = program.functions.iter()
+            .map(|function| (function.name.clone(), FunctionType {
+                params: function.params.iter().map(|param| param.ty.clone()).collect(),
+                variadic: false,
+                return_type: Box::new(function.return_type.clone()),
+            }))
+            .chain(externs)
+            .collect();
+        for external in program.externs.iter() {
+            module_gen.declare_extern(external)?;
+        }
+        for function in program.functions.iter() {
+            module_gen.declare_function(function)?;
+        }
+        for function in program.functions {
+            module_gen.generate_function(function)?;
+        }
+
+        let mut statements = match program.entry {
+            Entry::Script(statements) => statements,
+            Entry::Function(entry) => return Err(CodegenError::InvalidFunction(entry)),
+        };
+        let trailing = trailing_expression(&mut statements);
+        let result = trailing.as_ref().map_or(Type::Unit, |value| value.ty.clone());
+        let defined = statements.iter()
+            .filter_map(|statement| match &statement.kind {
+                TypedStmtKind::Define { name, mutable, value } => {
+                    Some((name.clone(), Variable { ty: value.ty.clone(), mutable: *mutable, span: None }))
+                },
+                _ => None,
+            })
+            .collect::<Vec<(String, Variable)>>();
+
+        let fn_type = match result {
+            Type::Struct(_) => module_gen.parent.context.i8_type().ptr_type(AddressSpace::default()).fn_type(&[], false),
+            _ => module_gen.llvm_fn_type(&[], false, &result)?,
+        };
+        let function = module_gen.module.add_function(name, fn_type, None);
+        if result == Type::Bool {
+            // the shell reads the result as a rust `bool`, so the upper bits of the register have to be cleared
+            let zeroext = Attribute::get_named_enum_kind_id("zeroext");
+            function.add_attribute(AttributeLoc::Return, module_gen.parent.context.create_enum_attribute(zeroext, 0));
+        }
+        let fn_gen = FunctionGenerator::create_repl_generator(&module_gen, function);
+        for (variable, symbol) in symbols.iter() {
+            let ty = &session.variables[variable].ty;
+            let ty = module_gen.llvm_type(ty)?
+                .ok_or_else(|| CodegenError::UnsupportedType(ty.clone()))?;
+            let global = module_gen.module.add_global(ty, None, symbol.as_str());
+            fn_gen.define_symbol(variable.clone(), global.as_pointer_value());
+        }
+
+        fn_gen.generate_statements(statements)
+            .and_then(|_| trailing.map(|value| generate_expression(value, &fn_gen)).transpose())
+            .and_then(|value| {
+                let value = match (value.flatten(), &result) {
+                    (Some(value), Type::Struct(_)) => Some(store_result(value, &fn_gen)),
+                    (value, _) => value,
+                };
+                fn_gen.build_return_value(value)
+            })
+            .map_err(|error| error.in_function(name))?;
+        let variables = defined.into_iter()
+            .map(|(name, variable)| {
+                let global = fn_gen.resolve_symbol(&name)?;
+                let symbol = global.get_name().to_string_lossy().to_string();
+                Ok((name, (variable, symbol)))
+            })
+            .collect::<CodegenResult<_>>()?;
+        fn_gen.complete().map_err(|error| error.in_function(name))?;
+        module_gen.optimize();
+
+        let structs = program.structs;
+        let struct_types = module_gen.structs.take();
+        Ok(ReplModule { module: module_gen.module.clone(), function: name.to_string(), result, variables, functions, structs, struct_types })
+    }
+}
+
+/// Declares a function of another module with the same type and attributes.
+fn redeclare_function<'gen>(module: &Module<'gen>, declaration: FunctionValue<'gen>) -> FunctionValue<'gen> {
+    let name = declaration.get_name().to_string_lossy();
+    let function = module.add_function(&name, declaration.get_type(), None);
+    let locations = (0..declaration.count_params())
+        .map(AttributeLoc::Param)
+        .chain(std::iter::once(AttributeLoc::Return));
+    for location in locations {
+        for attribute in declaration.attributes(location) {
+            function.add_attribute(location, attribute);
+        }
+    }
+    function
+}
+
+/// Copies the value to a global of the module and returns a pointer to it.
+fn store_result<'gen>(value: BasicValueEnum<'gen>, fn_gen: &FunctionGenerator<'gen,'_,'_>) -> BasicValueEnum<'gen> {
+    let global = fn_gen.build_variable(value.get_type(), "result");
+    fn_gen.builder.build_store(global, value);
+    let pointer_type = fn_gen.parent.parent.context.i8_type().ptr_type(AddressSpace::default());
+    fn_gen.builder.build_pointer_cast(global, pointer_type, "result").into()
+}
+
+/// Removes a trailing expression with a value from the statements, its value is the result of the input.
+fn trailing_expression(statements: &mut Vec<TypedStmt>) -> Option<TypedExpr> {
+    match statements.last() {
+        Some(TypedStmt { kind: TypedStmtKind::Expression(value), .. }) if !value.ty.is_unit() => {},
+        _ => return None,
+    }
+    match statements.pop() {
+        Some(TypedStmt { kind: TypedStmtKind::Expression(value), .. }) => Some(value),
+        _ => None,
+    }
+}
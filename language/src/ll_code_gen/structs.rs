@@ -0,0 +1,27 @@
+use inkwell::values::{AggregateValueEnum, BasicValue, BasicValueEnum};
+use crate::ll_code_gen::{CodegenError, CodegenResult};
+use crate::ll_code_gen::expression::generate_value;
+use crate::ll_code_gen::function_generator::FunctionGenerator;
+use crate::typeck::TypedExpr;
+
+/// Builds the struct value field by field, the values are evaluated in source order.
+pub fn generate_struct_literal<'gen>(name: String, fields: Vec<(usize, TypedExpr)>, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let struct_type = scope.parent.struct_type(name.as_str())?;
+    let mut value = AggregateValueEnum::from(struct_type.get_undef());
+    for (index, field) in fields {
+        let field = generate_value(field, scope)?;
+        value = scope.builder.build_insert_value(value, field, index as u32, "struct")
+            .ok_or_else(|| CodegenError::UndefinedStruct(name.clone()))?;
+    }
+    Ok(value.as_basic_value_enum())
+}
+
+pub fn generate_field_access<'gen>(value: TypedExpr, index: usize, scope: &FunctionGenerator<'gen,'_,'_>) -> CodegenResult<BasicValueEnum<'gen>> {
+    let ty = value.ty.clone();
+    let value = match generate_value(value, scope)? {
+        BasicValueEnum::StructValue(value) => value,
+        _ => return Err(CodegenError::UnsupportedType(ty)),
+    };
+    scope.builder.build_extract_value(value, index as u32, "field")
+        .ok_or(CodegenError::UnsupportedType(ty))
+}
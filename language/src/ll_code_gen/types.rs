@@ -0,0 +1,102 @@
+use inkwell::AddressSpace;
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType as LLVMFunctionType, PointerType, StructType};
+use crate::ll_code_gen::{CodegenError, CodegenResult, ModuleGenerator};
+use crate::typeck::{ExternType, FunctionType, StructType as CurryStructType, Type, TypedExtern};
+
+impl <'gen: 'module, 'module> ModuleGenerator<'gen, 'module> {
+
+    /// The llvm representation of values of a curry type, `None` for unit.
+    pub fn llvm_type(&self, ty: &Type) -> CodegenResult<Option<BasicTypeEnum<'gen>>> {
+        let context = self.parent.context;
+        match ty {
+            Type::Str => Ok(Some(context.i8_type().ptr_type(AddressSpace::default()).into())),
+            Type::Int => Ok(Some(context.i64_type().into())),
+            Type::Bool => Ok(Some(context.bool_type().into())),
+            Type::Unit => Ok(None),
+            Type::Function(signature) => Ok(Some(self.closure_type(signature)?.into())),
+            Type::Struct(name) => Ok(Some(self.struct_type(name)?.into())),
+            it => Err(CodegenError::UnsupportedType(it.clone())),
+        }
+    }
+
+    /// A struct declared by the module, or given to it by [`ModuleGenerator::structs`].
+    pub fn struct_type(&self, name: &str) -> CodegenResult<StructType<'gen>> {
+        self.structs.borrow().get(name).copied()
+            .ok_or_else(|| CodegenError::UndefinedStruct(name.to_string()))
+    }
+
+    /// Declares the structs as named types of the context. All names are declared before the bodies are set, fields
+    /// can have the type of any of the structs.
+    pub fn declare_structs(&self, structs: &[CurryStructType]) -> CodegenResult<()> {
+        let context = self.parent.context;
+        let declared = structs.iter()
+            .map(|structure| context.opaque_struct_type(&structure.name))
+            .collect::<Vec<_>>();
+        for (struct_type, structure) in declared.iter().zip(structs) {
+            self.structs.borrow_mut().insert(structure.name.clone(), *struct_type);
+        }
+        for (struct_type, structure) in declared.into_iter().zip(structs) {
+            let fields = structure.fields.iter()
+                .map(|(_, ty)| self.llvm_type(ty)?.ok_or_else(|| CodegenError::UnsupportedType(ty.clone())))
+                .collect::<CodegenResult<Vec<_>>>()?;
+            struct_type.set_body(&fields, false);
+        }
+        Ok(())
+    }
+
+    pub fn llvm_fn_type(&self, params: &[Type], variadic: bool, return_type: &Type) -> CodegenResult<LLVMFunctionType<'gen>> {
+        self.build_fn_type(Vec::new(), params, variadic, return_type)
+    }
+
+    /// Function values are a pair of the function and its environment: `{ ret (i8*, params...)*, i8* }`.
+    pub fn closure_type(&self, signature: &FunctionType) -> CodegenResult<StructType<'gen>> {
+        let function = self.closure_fn_type(signature)?.ptr_type(AddressSpace::default());
+        Ok(self.parent.context.struct_type(&[function.into(), self.environment_type().into()], false))
+    }
+
+    /// The function of a closure takes the environment before the parameters of the signature.
+    pub fn closure_fn_type(&self, signature: &FunctionType) -> CodegenResult<LLVMFunctionType<'gen>> {
+        let environment = vec![self.environment_type().into()];
+        self.build_fn_type(environment, &signature.params, false, &signature.return_type)
+    }
+
+    /// Captured values are only known to the functions of the closures, everyone else passes an untyped pointer.
+    pub fn environment_type(&self) -> PointerType<'gen> {
+        self.parent.context.i8_type().ptr_type(AddressSpace::default())
+    }
+
+    fn build_fn_type(&self, mut param_types: Vec<BasicMetadataTypeEnum<'gen>>, params: &[Type], variadic: bool, return_type: &Type) -> CodegenResult<LLVMFunctionType<'gen>> {
+        for param in params {
+            let param_type = self.llvm_type(param)?
+                .ok_or_else(|| CodegenError::UnsupportedType(param.clone()))?;
+            param_types.push(param_type.into());
+        }
+
+        Ok(match self.llvm_type(return_type)? {
+            Some(return_type) => return_type.fn_type(&param_types, variadic),
+            None => self.parent.context.void_type().fn_type(&param_types, variadic),
+        })
+    }
+
+    /// The signature of an extern as the c function declares it, a c `int` is an `i32`.
+    pub fn extern_fn_type(&self, external: &TypedExtern) -> CodegenResult<LLVMFunctionType<'gen>> {
+        let param_types = external.params.iter()
+            .map(|param| self.extern_type(param)?
+                .map(BasicMetadataTypeEnum::from)
+                .ok_or_else(|| CodegenError::UnsupportedType(param.curry_type())))
+            .collect::<CodegenResult<Vec<BasicMetadataTypeEnum>>>()?;
+
+        Ok(match self.extern_type(&external.return_type)? {
+            Some(return_type) => return_type.fn_type(&param_types, external.variadic),
+            None => self.parent.context.void_type().fn_type(&param_types, external.variadic),
+        })
+    }
+
+    fn extern_type(&self, ty: &ExternType) -> CodegenResult<Option<BasicTypeEnum<'gen>>> {
+        match ty {
+            ExternType::Value(ty) => self.llvm_type(ty),
+            ExternType::CInt => Ok(Some(self.parent.context.i32_type().into())),
+            ExternType::CLong => Ok(Some(self.parent.context.i64_type().into())),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use thiserror::Error;
 use crate::parser::curry_pest::Rule;
+use crate::parser::Span;
 
 #[derive(Error,Debug)]
 pub enum IllegalSourceState {
@@ -7,4 +8,19 @@ pub enum IllegalSourceState {
     IllegalRule {
         rule: Rule,
     },
-}
\ No newline at end of file
+    #[error("integer literal {literal} is out of range")]
+    IntegerOutOfRange {
+        literal: String,
+        span: Span,
+    },
+    #[error("unknown escape sequence {sequence}")]
+    UnknownEscape {
+        sequence: String,
+        span: Span,
+    },
+    #[error("invalid unicode escape {sequence}")]
+    InvalidUnicodeEscape {
+        sequence: String,
+        span: Span,
+    },
+}
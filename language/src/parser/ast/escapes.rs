@@ -0,0 +1,120 @@
+//! Decoding of escape sequences in string literals.
+//!
+//! The grammar accepts a backslash followed by any character, so unknown escapes are reported with their location
+//! instead of as a generic syntax error.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+use crate::parser::ast::errors::IllegalSourceState;
+use crate::parser::Span;
+
+/// Most code points a `\u{...}` escape may have.
+const MAX_UNICODE_DIGITS: usize = 6;
+
+/// Decodes the content of a string literal. `offset` is the position of the content in the source, so errors
+/// point at the offending escape.
+pub fn decode_escapes(raw: &str, offset: usize) -> Result<String, IllegalSourceState> {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    while let Some((start, char)) = chars.next() {
+        if char != '\\' {
+            decoded.push(char);
+            continue;
+        }
+
+        let escaped = match chars.next().map(|(_, escape)| escape) {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('0') => '\0',
+            Some('u') => decode_unicode(&mut chars).ok_or_else(|| {
+                let end = position(&mut chars, raw);
+                IllegalSourceState::InvalidUnicodeEscape {
+                    sequence: raw[start..end].to_string(),
+                    span: Span::new(offset + start, offset + end),
+                }
+            })?,
+            _ => {
+                let end = position(&mut chars, raw);
+                return Err(IllegalSourceState::UnknownEscape {
+                    sequence: raw[start..end].to_string(),
+                    span: Span::new(offset + start, offset + end),
+                });
+            },
+        };
+        decoded.push(escaped);
+    }
+    Ok(decoded)
+}
+
+/// Decodes the code point of `\uXXXX` or `\u{X}` with up to six hex digits, `None` if it is malformed or no valid
+/// unicode scalar value.
+fn decode_unicode(chars: &mut Peekable<CharIndices<'_>>) -> Option<char> {
+    let mut digits = String::new();
+    if chars.next_if(|(_, char)| *char == '{').is_some() {
+        while let Some((_, digit)) = chars.next_if(|(_, char)| *char != '}' && *char != '"') {
+            digits.push(digit);
+        }
+        chars.next_if(|(_, char)| *char == '}')?;
+        if digits.is_empty() || digits.len() > MAX_UNICODE_DIGITS {
+            return None;
+        }
+    } else {
+        for _ in 0..4 {
+            let (_, digit) = chars.next_if(|(_, char)| char.is_ascii_hexdigit())?;
+            digits.push(digit);
+        }
+    }
+
+    if !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+}
+
+/// Offset of the next character in `raw`, its length at the end.
+fn position(chars: &mut Peekable<CharIndices<'_>>, raw: &str) -> usize {
+    chars.peek().map_or(raw.len(), |(position, _)| *position)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_decodes_simple_escapes() {
+        assert_eq!("a\"b\\c\nd\re\tf\u{8}g\u{c}h\0", decode_escapes(r#"a\"b\\c\nd\re\tf\bg\fh\0"#, 0).unwrap());
+    }
+
+    #[test]
+    fn escaped_backslash_is_not_part_of_the_next_escape() {
+        assert_eq!("\\n", decode_escapes(r"\\n", 0).unwrap());
+    }
+
+    #[test]
+    fn it_decodes_unicode_escapes() {
+        assert_eq!("é€😀!", decode_escapes(r"é\u{20AC}\u{1F600}\u{21}", 0).unwrap());
+    }
+
+    #[test]
+    fn it_reports_invalid_escapes_with_their_location() {
+        match decode_escapes(r"ok \q", 10) {
+            Err(IllegalSourceState::UnknownEscape { sequence, span }) => {
+                assert_eq!(r"\q", sequence);
+                assert_eq!(Span::new(13, 15), span);
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        for invalid in [r"\u{110000}", r"\u{D800}", r"\u{}", r"\u{1234567}", r"\u{12", r"\u12", r"\u{xyz}"] {
+            assert!(
+                matches!(decode_escapes(invalid, 0), Err(IllegalSourceState::InvalidUnicodeEscape { .. })),
+                "{} should be invalid", invalid
+            );
+        }
+    }
+}
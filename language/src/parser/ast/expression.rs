@@ -0,0 +1,425 @@
+use std::convert::TryFrom;
+use pest::iterators::Pair;
+use crate::parser::precedence::PrecedenceClimber;
+use crate::parser::Span;
+use crate::parser::ast::escapes::decode_escapes;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Ident(Ident),
+    Call { callee: Ident, args: Vec<Expr> },
+    /// Calls the function value returned by another expression.
+    Apply { callee: Box<Expr>, args: Vec<Expr> },
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    Binary { op: BinaryOp, lhs: Box<Expr>, rhs: Box<Expr> },
+    /// An `else if` chain is nested in the else block.
+    If { condition: Box<Expr>, then_block: Block, else_block: Option<Block> },
+    /// An anonymous function. An expression body is a block with that expression.
+    Lambda { params: Vec<Param>, return_type: Option<Ident>, body: Block },
+    /// Creates a struct value, the fields are in source order.
+    StructLiteral { name: Ident, fields: Vec<(Ident, Expr)> },
+    /// Reads the field of a struct value.
+    Field { value: Box<Expr>, field: Ident },
+}
+
+/// Literal values, already decoded from their source representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Literal {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// Short-circuiting logical and.
+    And,
+    /// Short-circuiting logical or.
+    Or,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessEqual
+            | BinaryOp::Greater | BinaryOp::GreaterEqual)
+    }
+
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for Ident {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        match pair.as_rule() {
+            Rule::symbol_ref | Rule::type_name => Ok(Ident { name: pair.as_str().to_string(), span: pair.as_span().into() }),
+            rule => Err(InvalidParserState::illegal_rule(rule).into()),
+        }
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for Expr {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        let span = Span::from(pair.as_span());
+        let kind = match pair.as_rule() {
+            Rule::expression => return PrecedenceClimber::climb(
+                pair.syntax(),
+                Expr::try_from,
+                Self::unary,
+                Self::field,
+                Self::binary,
+            ),
+            Rule::string_literal => {
+                let raw_string = pair.syntax().expect_unique_pair();
+                let offset = raw_string.as_span().start();
+                ExprKind::Literal(Literal::Str(decode_escapes(raw_string.as_str(), offset)?))
+            },
+            Rule::integer_literal => {
+                let value = parse_user_integer(pair.as_str())
+                    .ok_or_else(|| IllegalSourceState::IntegerOutOfRange { literal: pair.as_str().to_string(), span })?;
+                ExprKind::Literal(Literal::Int(value))
+            },
+            Rule::boolean_literal => ExprKind::Literal(Literal::Bool(pair.as_str() == "true")),
+            Rule::symbol_ref => ExprKind::Ident(Ident::try_from(pair)?),
+            Rule::if_expression => {
+                let mut inner = pair.syntax()
+                    .filter(|it| !matches!(it.as_rule(), Rule::IF_KEYWORD | Rule::ELSE_KEYWORD));
+                let condition = Expr::try_from(inner.next().expect("if_expression->expression missing"))?;
+                let then_block = Block::try_from(inner.next().expect("if_expression->block missing"))?;
+                let else_block = inner.next()
+                    .map(|branch| match branch.as_rule() {
+                        Rule::if_expression => {
+                            let nested = Expr::try_from(branch)?;
+                            let span = nested.span;
+                            Ok(Block { statements: vec![Stmt { kind: StmtKind::Expression(nested), span }], span })
+                        },
+                        _ => Block::try_from(branch),
+                    })
+                    .transpose()?;
+                ExprKind::If { condition: Box::new(condition), then_block, else_block }
+            },
+            Rule::lambda => {
+                let mut params = Vec::new();
+                let mut return_type = None;
+                let mut body = None;
+                for inner in pair.syntax() {
+                    match inner.as_rule() {
+                        Rule::lambda_params => params = inner.syntax()
+                            .map(Param::try_from)
+                            .collect::<Result<Vec<Param>, InvalidParserState>>()?,
+                        Rule::return_type => return_type = Some(inner.syntax().expect_unique_pair().try_into()?),
+                        Rule::block => body = Some(Block::try_from(inner)?),
+                        _ => {
+                            let value = Expr::try_from(inner)?;
+                            let span = value.span;
+                            body = Some(Block { statements: vec![Stmt { kind: StmtKind::Expression(value), span }], span });
+                        },
+                    }
+                }
+                ExprKind::Lambda { params, return_type, body: body.expect("lambda->body missing") }
+            },
+            Rule::struct_literal => {
+                let mut inner = pair.syntax();
+                let name = Ident::try_from(inner.next().expect("struct_literal->type_name missing"))?;
+                let fields = inner
+                    .map(|field_init| {
+                        let mut inner = field_init.syntax();
+                        let field = Ident::try_from(inner.next().expect("field_init->symbol_ref missing"))?;
+                        let value = Expr::try_from(inner.next().expect("field_init->expression missing"))?;
+                        Ok((field, value))
+                    })
+                    .collect::<Result<Vec<_>, InvalidParserState>>()?;
+                ExprKind::StructLiteral { name, fields }
+            },
+            Rule::function_call => {
+                let mut inner = pair.syntax();
+                let callee = Ident::try_from(inner.next().expect("function_call->symbol_ref missing"))?;
+                let mut kind = ExprKind::Call { callee, args: Self::arguments(inner.next().expect("function_call->fn_args missing"))? };
+                for args in inner {
+                    let callee = Expr { kind, span: Span::new(span.start, args.as_span().start()) };
+                    kind = ExprKind::Apply { callee: Box::new(callee), args: Self::arguments(args)? };
+                }
+                kind
+            },
+            rule => return Err(InvalidParserState::illegal_rule_with_context(rule, "Tried to create expression".to_string()).into()),
+        };
+        Ok(Expr { kind, span })
+    }
+}
+
+impl Expr {
+    fn arguments(fn_args: Pair<Rule>) -> Result<Vec<Expr>, InvalidParserState> {
+        fn_args.syntax()
+            .map(Expr::try_from)
+            .collect()
+    }
+
+    fn unary(operator: Pair<Rule>, operand: Expr) -> Result<Expr, InvalidParserState> {
+        let op = match operator.as_rule() {
+            Rule::negate => UnaryOp::Negate,
+            Rule::not => UnaryOp::Not,
+            rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+        };
+        let span = Span::from(operator.as_span()).to(operand.span);
+        Ok(Expr { kind: ExprKind::Unary { op, operand: Box::new(operand) }, span })
+    }
+
+    fn field(value: Expr, field: Pair<Rule>) -> Result<Expr, InvalidParserState> {
+        let span = value.span.to(field.as_span().into());
+        let field = Ident::try_from(field.syntax().expect_unique_pair())?;
+        Ok(Expr { kind: ExprKind::Field { value: Box::new(value), field }, span })
+    }
+
+    fn binary(lhs: Expr, operator: Pair<Rule>, rhs: Expr) -> Result<Expr, InvalidParserState> {
+        let span = lhs.span.to(rhs.span);
+        if operator.as_rule() == Rule::pipe {
+            return Ok(Expr { kind: Self::pipe(lhs, rhs), span });
+        }
+
+        let op = match operator.as_rule() {
+            Rule::add => BinaryOp::Add,
+            Rule::subtract => BinaryOp::Subtract,
+            Rule::multiply => BinaryOp::Multiply,
+            Rule::divide => BinaryOp::Divide,
+            Rule::modulo => BinaryOp::Modulo,
+            Rule::equal => BinaryOp::Equal,
+            Rule::not_equal => BinaryOp::NotEqual,
+            Rule::less => BinaryOp::Less,
+            Rule::less_equal => BinaryOp::LessEqual,
+            Rule::greater => BinaryOp::Greater,
+            Rule::greater_equal => BinaryOp::GreaterEqual,
+            Rule::and => BinaryOp::And,
+            Rule::or => BinaryOp::Or,
+            rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+        };
+        Ok(Expr { kind: ExprKind::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }, span })
+    }
+
+    /// `x |> f` is `f(x)`. With currying, `x |> f(1)` is `f(1)(x)`, so the piped value is the last argument.
+    fn pipe(value: Expr, function: Expr) -> ExprKind {
+        match function.kind {
+            ExprKind::Ident(callee) => ExprKind::Call { callee, args: vec![value] },
+            _ => ExprKind::Apply { callee: Box::new(function), args: vec![value] },
+        }
+    }
+}
+
+/// The sign is part of the literal, so `-9223372036854775808` is in range.
+fn parse_user_integer(raw_integer: &str) -> Option<i64> {
+    let digits = raw_integer.replace('_', "");
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits.as_str()),
+    };
+    let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(&format!("{}{}", sign, hex), 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(&format!("{}{}", sign, binary), 2)
+    } else {
+        format!("{}{}", sign, digits).parse::<i64>()
+    };
+    parsed.ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pest::Parser;
+    use std::convert::TryInto;
+    use crate::parser::curry_pest::{CurryParser, PairsHelper};
+
+    fn parse_expression(input: &str) -> Expr {
+        CurryParser::parse(Rule::expression, input).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap()
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        let expression = parse_expression("1 + 2 * 3");
+
+        match expression.kind {
+            ExprKind::Binary { op: BinaryOp::Add, rhs, .. } => {
+                assert!(matches!(rhs.kind, ExprKind::Binary { op: BinaryOp::Multiply, .. }))
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn comparison_binds_weaker_than_arithmetic() {
+        let expression = parse_expression("a + 1 < b || !done && a == b");
+
+        match expression.kind {
+            ExprKind::Binary { op: BinaryOp::Or, lhs, rhs } => {
+                assert!(matches!(lhs.kind, ExprKind::Binary { op: BinaryOp::Less, .. }));
+                assert!(matches!(rhs.kind, ExprKind::Binary { op: BinaryOp::And, .. }));
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn else_if_is_nested_in_else_block() {
+        let expression = parse_expression("if a { 1 } else if b { 2 } else { 3 }");
+
+        match expression.kind {
+            ExprKind::If { else_block: Some(else_block), .. } => {
+                assert!(matches!(else_block.statements[0].kind, StmtKind::Expression(Expr { kind: ExprKind::If { .. }, .. })));
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn keywords_are_no_identifiers() {
+        assert_eq!(ExprKind::Literal(Literal::Bool(true)), parse_expression("true").kind);
+        assert!(matches!(parse_expression("iffy").kind, ExprKind::Ident(_)));
+        assert!(CurryParser::parse(Rule::assignment, "if = 1").is_err());
+    }
+
+    #[test]
+    fn lambda_bodies_are_blocks_or_expressions() {
+        match parse_expression("|a, b: int| a + b").kind {
+            ExprKind::Lambda { params, return_type: None, body } => {
+                assert_eq!(vec!["a", "b"], params.iter().map(|param| param.name.name.as_str()).collect::<Vec<_>>());
+                assert!(matches!(body.statements[0].kind, StmtKind::Expression(Expr { kind: ExprKind::Binary { .. }, .. })));
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+
+        match parse_expression("|| -> int { return 1 }").kind {
+            ExprKind::Lambda { params, return_type: Some(return_type), body } => {
+                assert!(params.is_empty());
+                assert_eq!("int", return_type.name);
+                assert!(matches!(body.statements[0].kind, StmtKind::Return(_)));
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn pipe_binds_weakest_and_calls_from_left_to_right() {
+        let expression = parse_expression("x + 1 |> double |> add(2)");
+
+        match expression.kind {
+            ExprKind::Apply { callee, args } => {
+                assert!(matches!(callee.kind, ExprKind::Call { ref callee, .. } if callee.name == "add"));
+                match &args[0].kind {
+                    ExprKind::Call { callee, args } => {
+                        assert_eq!("double", callee.name);
+                        assert!(matches!(args[0].kind, ExprKind::Binary { op: BinaryOp::Add, .. }));
+                    },
+                    kind => panic!("unexpected argument {:?}", kind)
+                }
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn field_access_binds_tighter_than_prefix_operators() {
+        let expression = parse_expression("-team.lead.age");
+
+        match expression.kind {
+            ExprKind::Unary { op: UnaryOp::Negate, operand } => match operand.kind {
+                ExprKind::Field { value, field } => {
+                    assert_eq!("age", field.name);
+                    assert!(matches!(value.kind, ExprKind::Field { ref field, .. } if field.name == "lead"));
+                },
+                kind => panic!("unexpected operand {:?}", kind)
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn struct_literals_take_fields_in_source_order() {
+        let expression = parse_expression("Point {\n\ty: 2,\n\tx: 1 + 1,\n}");
+
+        match expression.kind {
+            ExprKind::StructLiteral { name, fields } => {
+                assert_eq!("Point", name.name);
+                assert_eq!(vec!["y", "x"], fields.iter().map(|(field, _)| field.name.as_str()).collect::<Vec<_>>());
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+        assert!(matches!(parse_expression("0..count").kind, ExprKind::Literal(Literal::Int(0))));
+    }
+
+    #[test]
+    fn call_arguments_are_expressions() {
+        let expression = parse_expression(r#"greet("Chuck", age + 1)"#);
+
+        match expression.kind {
+            ExprKind::Call { callee, args } => {
+                assert_eq!("greet", callee.name);
+                assert_eq!(ExprKind::Literal(Literal::Str("Chuck".to_string())), args[0].kind);
+                assert_eq!(Span::new(15, 22), args[1].span);
+            },
+            kind => panic!("unexpected expression {:?}", kind)
+        }
+    }
+}
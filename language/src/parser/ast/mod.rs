@@ -1,15 +1,28 @@
+pub mod program;
 pub mod statement;
+pub mod expression;
 pub mod errors;
+mod escapes;
 
 pub use crate::parser::errors::*;
 pub use crate::parser::curry_pest::*;
+pub use crate::parser::ast::program::*;
 pub use crate::parser::ast::statement::*;
+pub use crate::parser::ast::expression::*;
 
-use anyhow::*;
+use std::convert::TryInto;
 
-pub fn parse_to_ast(input: &str) -> Result<Pair<Rule>,Error> {
-    CurryParser::parse(Rule::source, input)
-        .context("parsing cst")?
-        .unique_pair()?
-        .unique_inner()
-}
\ No newline at end of file
+pub fn parse_to_ast(input: &str) -> Result<Program,InvalidParserState> {
+    let source = CurryParser::parse(Rule::source, input)?
+        .expect_unique_pair()
+        .syntax()
+        .expect_unique_pair();
+
+    source.try_into()
+}
+
+pub fn parse_repl_input(input: &str) -> Result<ReplInput,InvalidParserState> {
+    CurryParser::parse(Rule::repl_input, input)?
+        .expect_unique_pair()
+        .try_into()
+}
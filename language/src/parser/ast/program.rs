@@ -0,0 +1,264 @@
+use std::convert::{TryFrom, TryInto};
+use pest::iterators::Pair;
+use crate::parser::Span;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub kind: ProgramKind,
+    /// Foreign functions declared with `extern`, at the top of scripts or between function declarations.
+    pub externs: Vec<ExternDecl>,
+    /// Struct types, declared like externs.
+    pub structs: Vec<StructDecl>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgramKind {
+    /// Top level statements, executed in order.
+    Script(Vec<Stmt>),
+    /// Function declarations, started by calling the `entry` function.
+    Functions { entry: Ident, functions: Vec<FunctionDecl> },
+}
+
+/// One input of the interactive shell, which declares functions or runs statements.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplInput {
+    Functions { externs: Vec<ExternDecl>, structs: Vec<StructDecl>, functions: Vec<FunctionDecl> },
+    Statements { externs: Vec<ExternDecl>, structs: Vec<StructDecl>, statements: Vec<Stmt> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDecl {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub return_type: Option<Ident>,
+    pub body: Vec<Stmt>,
+    /// Span of the braced body, its end locates a missing return value.
+    pub body_span: Span,
+    pub span: Span,
+}
+
+/// A function implemented outside of the program, e.g. by the c library. Its parameters are always typed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternDecl {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    /// Whether more arguments than parameters may be passed, declared with a trailing `...`.
+    pub variadic: bool,
+    pub return_type: Option<Ident>,
+    pub span: Span,
+}
+
+/// A struct type with named fields. Its fields are always typed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDecl {
+    pub name: Ident,
+    pub fields: Vec<Param>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Ident,
+    pub type_name: Option<Ident>,
+    pub span: Span,
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for Program {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        let span = Span::from(pair.as_span());
+        let mut externs = Vec::new();
+        let mut structs = Vec::new();
+        let kind = match pair.as_rule() {
+            Rule::script => {
+                let mut statements = Vec::new();
+                for inner in pair.syntax() {
+                    match inner.as_rule() {
+                        Rule::extern_declaration => externs.push(inner.try_into()?),
+                        Rule::struct_declaration => structs.push(inner.try_into()?),
+                        Rule::EOI => {},
+                        _ => statements.push(inner.try_into()?),
+                    }
+                }
+                ProgramKind::Script(statements)
+            },
+            Rule::program => {
+                let mut entry = None;
+                let mut functions = Vec::new();
+                for inner in pair.syntax() {
+                    match inner.as_rule() {
+                        Rule::entry_definition => entry = Some(inner.syntax().expect_unique_pair().try_into()?),
+                        Rule::function_declaration => functions.push(inner.try_into()?),
+                        Rule::extern_declaration => externs.push(inner.try_into()?),
+                        Rule::struct_declaration => structs.push(inner.try_into()?),
+                        Rule::EOI => {},
+                        rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+                    }
+                }
+                ProgramKind::Functions { entry: entry.expect("program->entry_definition missing"), functions }
+            },
+            rule => return Err(
+                InvalidParserState::illegal_rule_with_context(rule, "Tried to create program".to_string())
+                    .into()
+            ),
+        };
+        Ok(Program { kind, externs, structs, span })
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for ReplInput {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        if pair.as_rule() != Rule::repl_input {
+            return Err(InvalidParserState::illegal_rule(pair.as_rule()).into());
+        }
+
+        let mut externs = Vec::new();
+        let mut structs = Vec::new();
+        let mut functions = Vec::new();
+        for inner in pair.syntax() {
+            match inner.as_rule() {
+                Rule::script => {
+                    let Program { kind, externs, structs, .. } = Program::try_from(inner)?;
+                    match kind {
+                        ProgramKind::Script(statements) => return Ok(ReplInput::Statements { externs, structs, statements }),
+                        ProgramKind::Functions { .. } => unreachable!("script is parsed as statements"),
+                    }
+                },
+                Rule::function_declaration => functions.push(inner.try_into()?),
+                Rule::extern_declaration => externs.push(inner.try_into()?),
+                Rule::struct_declaration => structs.push(inner.try_into()?),
+                Rule::EOI => {},
+                rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+            }
+        }
+        Ok(ReplInput::Functions { externs, structs, functions })
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for FunctionDecl {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        if pair.as_rule() != Rule::function_declaration {
+            return Err(InvalidParserState::illegal_rule(pair.as_rule()).into());
+        }
+
+        let span = Span::from(pair.as_span());
+        let mut name = None;
+        let mut params = Vec::new();
+        let mut return_type = None;
+        let mut body = None;
+        for inner in pair.syntax() {
+            match inner.as_rule() {
+                Rule::symbol_ref => name = Some(inner.try_into()?),
+                Rule::fn_params => params = inner.syntax()
+                    .map(Param::try_from)
+                    .collect::<Result<Vec<Param>, InvalidParserState>>()?,
+                Rule::return_type => return_type = Some(inner.syntax().expect_unique_pair().try_into()?),
+                Rule::function_body => body = Some(inner),
+                rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+            }
+        }
+
+        let body = body.expect("function_declaration->function_body missing");
+        let body_span = Span::from(body.as_span());
+        Ok(FunctionDecl {
+            name: name.expect("function_declaration->symbol_ref missing"),
+            params,
+            return_type,
+            body: body.syntax()
+                .map(Stmt::try_from)
+                .collect::<Result<Vec<Stmt>, InvalidParserState>>()?,
+            body_span,
+            span,
+        })
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for ExternDecl {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        if pair.as_rule() != Rule::extern_declaration {
+            return Err(InvalidParserState::illegal_rule(pair.as_rule()).into());
+        }
+
+        let span = Span::from(pair.as_span());
+        let mut name = None;
+        let mut params = Vec::new();
+        let mut variadic = false;
+        let mut return_type = None;
+        for inner in pair.syntax() {
+            match inner.as_rule() {
+                Rule::EXTERN_KEYWORD => {},
+                Rule::symbol_ref => name = Some(inner.try_into()?),
+                Rule::extern_params => for param in inner.syntax() {
+                    match param.as_rule() {
+                        Rule::variadic => variadic = true,
+                        _ => params.push(param.try_into()?),
+                    }
+                },
+                Rule::return_type => return_type = Some(inner.syntax().expect_unique_pair().try_into()?),
+                rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+            }
+        }
+
+        Ok(ExternDecl {
+            name: name.expect("extern_declaration->symbol_ref missing"),
+            params,
+            variadic,
+            return_type,
+            span,
+        })
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for StructDecl {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        if pair.as_rule() != Rule::struct_declaration {
+            return Err(InvalidParserState::illegal_rule(pair.as_rule()).into());
+        }
+
+        let span = Span::from(pair.as_span());
+        let mut name = None;
+        let mut fields = Vec::new();
+        for inner in pair.syntax() {
+            match inner.as_rule() {
+                Rule::STRUCT_KEYWORD => {},
+                Rule::type_name => name = Some(inner.try_into()?),
+                Rule::struct_field => fields.push(inner.try_into()?),
+                rule => return Err(InvalidParserState::illegal_rule(rule).into()),
+            }
+        }
+
+        Ok(StructDecl {
+            name: name.expect("struct_declaration->type_name missing"),
+            fields,
+            span,
+        })
+    }
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for Param {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        match pair.as_rule() {
+            Rule::fn_param | Rule::extern_param | Rule::struct_field => {
+                let span = Span::from(pair.as_span());
+                let mut pairs = pair.syntax();
+                let name = pairs.next().expect("fn_param->symbol_ref missing").try_into()?;
+                let type_name = pairs.next().map(Ident::try_from).transpose()?;
+                Ok(Param { name, type_name, span })
+            },
+            rule => Err(InvalidParserState::illegal_rule(rule).into()),
+        }
+    }
+}
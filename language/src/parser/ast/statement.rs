@@ -1,41 +1,132 @@
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use pest::iterators::Pair;
-use pest::Span;
+use crate::parser::Span;
 use super::*;
 
-#[derive(Debug)]
-pub struct Statement<'a> {
-    pub span: Span<'a>,
-    pub kind: StatementKind<'a>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-pub enum StatementKind<'a> {
-    Assignment(Pair<'a,Rule>),
-    FunctionCall(Pair<'a,Rule>),
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// `let` or `var` introducing a new variable.
+    Definition { mutability: Mutability, target: Ident, value: Expr },
+    /// Assigns a mutable variable, or defines an immutable one if the name is unknown.
+    Assignment { target: Ident, value: Expr },
+    /// Assigns a field of a mutable struct variable, `fields` is the path from the variable to the assigned field.
+    FieldAssignment { target: Ident, fields: Vec<Ident>, value: Expr },
+    Expression(Expr),
+    Return(Option<Expr>),
+    While { condition: Expr, body: Block },
+    /// Counts `variable` from `start` up to, but excluding, `end`.
+    For { variable: Ident, start: Expr, end: Expr, body: Block },
+    Break,
+    Continue,
+    /// A nested block, its variables are dropped at its end.
+    Block(Block),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutability {
+    /// Defined with `let`, or by assigning an unknown name.
+    Immutable,
+    /// Defined with `var`, it can be assigned again.
+    Mutable,
+}
+
+/// Braced statements, e.g. the branches of an `if`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub statements: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl <'a> TryFrom<Pair<'a,Rule>> for Block {
+    type Error = InvalidParserState;
+
+    fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
+        if pair.as_rule() != Rule::block {
+            return Err(InvalidParserState::illegal_rule(pair.as_rule()).into());
+        }
+        let span = Span::from(pair.as_span());
+        let statements = pair.syntax()
+            .map(Stmt::try_from)
+            .collect::<Result<Vec<Stmt>, InvalidParserState>>()?;
+        Ok(Block { statements, span })
+    }
+}
 
-impl <'a> TryFrom<Pair<'a,Rule>> for Statement<'a> {
+impl <'a> TryFrom<Pair<'a,Rule>> for Stmt {
     type Error = InvalidParserState;
 
     fn try_from(pair: Pair<'a, Rule>) -> Result<Self, Self::Error> {
-        let span = pair.as_span();
+        let span = Span::from(pair.as_span());
         let kind = match pair.as_rule() {
-            Rule::assignment => Result::Ok(StatementKind::Assignment(pair)),
-            Rule::function_call => Result::Ok(StatementKind::FunctionCall(pair)),
-            rule => Err(
+            Rule::assignment => {
+                let mut pairs = pair.syntax();
+                let target = pairs.next().expect("assignment->symbol_name missing").try_into()?;
+                let value = pairs.next().expect("assignment->expression missing").try_into()?;
+                StmtKind::Assignment { target, value }
+            },
+            Rule::field_assignment => {
+                let mut pairs = pair.syntax().collect::<Vec<_>>();
+                let value = pairs.pop().expect("field_assignment->expression missing").try_into()?;
+                let mut pairs = pairs.into_iter();
+                let target = pairs.next().expect("field_assignment->symbol_name missing").try_into()?;
+                let fields = pairs
+                    .map(|field| Ident::try_from(field.syntax().expect_unique_pair()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                StmtKind::FieldAssignment { target, fields, value }
+            },
+            Rule::definition => {
+                let mut pairs = pair.syntax();
+                let mutability = match pairs.next().expect("definition->keyword missing").as_rule() {
+                    Rule::VAR_KEYWORD => Mutability::Mutable,
+                    _ => Mutability::Immutable,
+                };
+                let target = pairs.next().expect("definition->symbol_name missing").try_into()?;
+                let value = pairs.next().expect("definition->expression missing").try_into()?;
+                StmtKind::Definition { mutability, target, value }
+            },
+            Rule::expression => StmtKind::Expression(pair.try_into()?),
+            Rule::return_statement => StmtKind::Return(
+                pair.syntax()
+                    .find(|it| it.as_rule() == Rule::expression)
+                    .map(Expr::try_from)
+                    .transpose()?
+            ),
+            Rule::block => StmtKind::Block(pair.try_into()?),
+            Rule::break_statement => StmtKind::Break,
+            Rule::continue_statement => StmtKind::Continue,
+            Rule::while_statement => {
+                let mut pairs = pair.syntax().filter(|it| it.as_rule() != Rule::WHILE_KEYWORD);
+                let condition = pairs.next().expect("while_statement->expression missing").try_into()?;
+                let body = pairs.next().expect("while_statement->block missing").try_into()?;
+                StmtKind::While { condition, body }
+            },
+            Rule::for_statement => {
+                let mut pairs = pair.syntax()
+                    .filter(|it| !matches!(it.as_rule(), Rule::FOR_KEYWORD | Rule::IN_KEYWORD));
+                let variable = pairs.next().expect("for_statement->symbol_ref missing").try_into()?;
+                let mut range = pairs.next().expect("for_statement->range missing").syntax();
+                let start = range.next().expect("range->expression missing").try_into()?;
+                let end = range.next().expect("range->expression missing").try_into()?;
+                let body = pairs.next().expect("for_statement->block missing").try_into()?;
+                StmtKind::For { variable, start, end, body }
+            },
+            rule => return Err(
                 InvalidParserState::illegal_rule_with_context(rule, "Tried to create statement".to_string())
                     .into()
             ),
         };
-        kind.map(|kind| Statement {kind, span})
-
+        Ok(Stmt {kind, span})
     }
 }
 
-impl <'a> fmt::Display for StatementKind<'a> {
+impl fmt::Display for StmtKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(self, f)
     }
@@ -45,12 +136,11 @@ impl <'a> fmt::Display for StatementKind<'a> {
 mod test {
     use super::*;
     use pest::Parser;
-    use std::convert::TryInto;
-    use crate::parser::curry_pest::{CurryParser,PairsHelper};
+    use crate::parser::curry_pest::{CurryParser, PairsHelper};
 
     #[test]
     fn assignment_is_statement() {
-        let statement: Statement = CurryParser::parse(
+        let statement: Stmt = CurryParser::parse(
             Rule::statement,
             r#"menu12a = "spicy""#
         ).unwrap()
@@ -58,14 +148,47 @@ mod test {
             .try_into().unwrap();
 
         match statement.kind {
-            StatementKind::Assignment(_pair) => {},
+            StmtKind::Assignment { .. } => {},
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn field_assignment_has_path_to_field() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            r#"team.lead.name = "Ada""#
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::FieldAssignment { target, fields, .. } => {
+                assert_eq!("team", target.name);
+                assert_eq!(vec!["lead", "name"], fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>());
+            },
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn var_is_mutable_definition() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            r#"var total = 0"#
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::Definition { mutability: Mutability::Mutable, target, .. } => assert_eq!("total", target.name),
             _ => panic!("unexpected stmt type {}", statement.kind)
         }
     }
 
     #[test]
     fn function_call_is_statement() {
-        let statement: Statement = CurryParser::parse(
+        let statement: Stmt = CurryParser::parse(
             Rule::statement,
             r#"printf("Hello World!")"#
         ).unwrap()
@@ -73,7 +196,70 @@ mod test {
             .try_into().unwrap();
 
         match statement.kind {
-            StatementKind::FunctionCall(_pair) => {},
+            StmtKind::Expression(Expr { kind: ExprKind::Call { .. }, .. }) => {},
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn arithmetic_is_expression_statement() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            r#"getAge() + 1"#
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::Expression(_expression) => {},
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn return_is_statement() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            r#"return name"#
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::Return(Some(_expression)) => {},
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn for_loop_is_statement() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            "for index in 0..count {\n\tbreak\n}"
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::For { variable, body, .. } => {
+                assert_eq!("index", variable.name);
+                assert!(matches!(body.statements[..], [Stmt { kind: StmtKind::Break, .. }]));
+            },
+            _ => panic!("unexpected stmt type {}", statement.kind)
+        }
+    }
+
+    #[test]
+    fn return_prefixed_variable_is_expression_statement() {
+        let statement: Stmt = CurryParser::parse(
+            Rule::statement,
+            r#"returnValue"#
+        ).unwrap()
+            .unique_pair().unwrap()
+            .try_into().unwrap();
+
+        match statement.kind {
+            StmtKind::Expression(_expression) => {},
             _ => panic!("unexpected stmt type {}", statement.kind)
         }
     }
@@ -9,22 +9,12 @@ use pest_derive::Parser;
 #[grammar = "parser/grammar.pest"]
 pub struct CurryParser;
 
-pub trait PairHelper<'i> {
-    fn unique_inner(self) -> anyhow::Result<Pair<'i, Rule>>;
-}
-
-impl <'i> PairHelper<'i> for Pair<'i,Rule> {
-    fn unique_inner(self) -> anyhow::Result<Pair<'i, Rule>> {
-        self.into_inner().unique_pair()
-    }
-}
-
 pub trait PairsHelper<'a> : Iterator + Sized {
     fn unique_pair(&mut self) -> anyhow::Result<Pair<'a, Rule>>;
     fn expect_unique_pair(&mut self) -> Pair<'a, Rule>;
 }
 
-impl<'a> PairsHelper<'a> for Pairs<'a,Rule> {
+impl<'a, I: Iterator<Item = Pair<'a, Rule>>> PairsHelper<'a> for I {
     fn unique_pair(&mut self) -> anyhow::Result<Pair<'a, Rule>> {
         match self.next() {
             Some(first) => {
@@ -45,6 +35,24 @@ impl<'a> PairsHelper<'a> for Pairs<'a,Rule> {
     }
 }
 
+/// Children of a pair without comments.
+pub type Syntax<'a> = std::iter::Filter<Pairs<'a, Rule>, fn(&Pair<'a, Rule>) -> bool>;
+
+/// Comments are kept in the parse tree as trivia, e.g. for formatting, but carry no meaning for the ast.
+pub trait PairHelper<'a> {
+    fn syntax(self) -> Syntax<'a>;
+}
+
+impl<'a> PairHelper<'a> for Pair<'a, Rule> {
+    fn syntax(self) -> Syntax<'a> {
+        self.into_inner().filter(is_syntax as fn(&Pair<'a, Rule>) -> bool)
+    }
+}
+
+pub fn is_syntax(pair: &Pair<'_, Rule>) -> bool {
+    pair.as_rule() != Rule::COMMENT
+}
+
 impl fmt::Display for Rule {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(self, f)
@@ -72,4 +80,77 @@ mod test {
         ).unwrap()
             .unique_pair().unwrap();
     }
+
+    #[test]
+    fn it_parses_function_declaration_with_parameters() {
+        let declaration = CurryParser::parse(
+            Rule::function_declaration,
+            r#"func greet(name, times: int) { printf("Hi %s", name) }"#
+        ).unwrap()
+            .unique_pair().unwrap();
+
+        let params: Vec<&str> = declaration.into_inner()
+            .find(|pair| pair.as_rule() == Rule::fn_params).unwrap()
+            .into_inner()
+            .map(|param| param.as_str())
+            .collect();
+        assert_eq!(vec!["name", "times: int"], params);
+    }
+
+    #[test]
+    fn it_keeps_comments_as_trivia() {
+        let script = CurryParser::parse(
+            Rule::script,
+            "// greeting\nprintf(\"hi\") /* outer /* nested */ */\n"
+        ).unwrap()
+            .unique_pair().unwrap();
+
+        let comments: Vec<&str> = script.clone().into_inner().flatten()
+            .filter(|pair| pair.as_rule() == Rule::COMMENT)
+            .map(|pair| pair.as_str())
+            .collect();
+        assert_eq!(vec!["// greeting", "/* outer /* nested */ */"], comments);
+        assert!(script.syntax().all(|pair| pair.as_rule() != Rule::COMMENT));
+    }
+
+    #[test]
+    fn it_parses_variadic_extern_declaration() {
+        let declaration = CurryParser::parse(
+            Rule::extern_declaration,
+            r#"extern func snprintf(buffer: str, size: clong, format: str, ...) -> cint"#
+        ).unwrap()
+            .unique_pair().unwrap();
+
+        let params: Vec<&str> = declaration.into_inner()
+            .find(|pair| pair.as_rule() == Rule::extern_params).unwrap()
+            .into_inner()
+            .map(|param| param.as_str())
+            .collect();
+        assert_eq!(vec!["buffer: str", "size: clong", "format: str", "..."], params);
+        assert!(CurryParser::parse(Rule::extern_declaration, "extern func puts(s)").is_err());
+    }
+
+    #[test]
+    fn it_parses_integer_literals() {
+        for literal in ["42", "1_000_000", "0xFF_ff", "0b1010_0101", "-9223372036854775808"] {
+            let pair = CurryParser::parse(Rule::integer_literal, literal).unwrap()
+                .unique_pair().unwrap();
+            assert_eq!(literal, pair.as_str());
+        }
+    }
+
+    #[test]
+    fn it_parses_arithmetic_expression() {
+        let expression = CurryParser::parse(
+            Rule::expression,
+            r#"-(a + 0x10) * 3 % count(x) - 1"#
+        ).unwrap()
+            .unique_pair().unwrap();
+
+        let rules: Vec<Rule> = expression.into_inner().map(|pair| pair.as_rule()).collect();
+        assert_eq!(
+            vec![Rule::negate, Rule::expression, Rule::multiply, Rule::integer_literal, Rule::modulo, Rule::function_call, Rule::subtract, Rule::integer_literal],
+            rules
+        );
+    }
 }
\ No newline at end of file
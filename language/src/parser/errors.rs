@@ -8,6 +8,8 @@ use crate::parser::curry_pest::Rule;
 pub enum InvalidParserState {
     #[error("{0}")]
     IllegalSourceState(IllegalSourceState),
+    #[error("{0}")]
+    Syntax(Box<pest::error::Error<Rule>>),
 }
 
 impl InvalidParserState {
@@ -23,4 +25,10 @@ impl From<IllegalSourceState> for InvalidParserState {
     fn from(value: IllegalSourceState) -> Self {
         InvalidParserState::IllegalSourceState(value)
     }
-}
\ No newline at end of file
+}
+
+impl From<pest::error::Error<Rule>> for InvalidParserState {
+    fn from(value: pest::error::Error<Rule>) -> Self {
+        InvalidParserState::Syntax(Box::new(value))
+    }
+}
@@ -1,9 +1,10 @@
 pub mod ast;
 pub mod errors;
+pub mod span;
 
 pub(crate) mod curry_pest;
+pub(crate) mod precedence;
 
-pub use pest::Parser;
-pub use ast::parse_to_ast;
+pub use ast::{parse_repl_input, parse_to_ast};
+pub use span::Span;
 
-pub(crate) use errors::*;
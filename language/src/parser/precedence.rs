@@ -0,0 +1,76 @@
+use std::iter::Peekable;
+use pest::iterators::Pair;
+use crate::parser::curry_pest::{Rule, Syntax};
+
+/// Binding power of binary operators, higher binds tighter. All binary operators are left associative.
+pub fn infix_precedence(rule: Rule) -> Option<u8> {
+    match rule {
+        Rule::pipe => Some(1),
+        Rule::or => Some(2),
+        Rule::and => Some(3),
+        Rule::equal | Rule::not_equal | Rule::less | Rule::less_equal | Rule::greater | Rule::greater_equal => Some(4),
+        Rule::add | Rule::subtract => Some(5),
+        Rule::multiply | Rule::divide | Rule::modulo => Some(6),
+        _ => None,
+    }
+}
+
+pub fn is_prefix_operator(rule: Rule) -> bool {
+    matches!(rule, Rule::negate | Rule::not)
+}
+
+pub fn is_postfix_operator(rule: Rule) -> bool {
+    matches!(rule, Rule::field)
+}
+
+/// Folds the flat operand/operator sequence of an `expression` pair according to operator precedence.
+/// Prefix operators bind tighter than any binary operator, postfix operators bind tighter than prefix operators.
+pub struct PrecedenceClimber<'i, P, U, S, B> {
+    pairs: Peekable<Syntax<'i>>,
+    primary: P,
+    prefix: U,
+    postfix: S,
+    infix: B,
+}
+
+impl<'i, T, E, P, U, S, B> PrecedenceClimber<'i, P, U, S, B>
+    where
+        P: FnMut(Pair<'i, Rule>) -> Result<T, E>,
+        U: FnMut(Pair<'i, Rule>, T) -> Result<T, E>,
+        S: FnMut(T, Pair<'i, Rule>) -> Result<T, E>,
+        B: FnMut(T, Pair<'i, Rule>, T) -> Result<T, E>,
+{
+    pub fn climb(pairs: Syntax<'i>, primary: P, prefix: U, postfix: S, infix: B) -> Result<T, E> {
+        let mut climber = PrecedenceClimber { pairs: pairs.peekable(), primary, prefix, postfix, infix };
+        climber.expression(0)
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<T, E> {
+        let mut lhs = self.unary()?;
+
+        while let Some(precedence) = self.pairs.peek()
+            .and_then(|pair| infix_precedence(pair.as_rule()))
+            .filter(|precedence| *precedence >= min_precedence)
+        {
+            let operator = self.pairs.next().expect("peeked operator");
+            let rhs = self.expression(precedence + 1)?;
+            lhs = (self.infix)(lhs, operator, rhs)?;
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<T, E> {
+        let pair = self.pairs.next().expect("expression requires an operand");
+        if is_prefix_operator(pair.as_rule()) {
+            let operand = self.unary()?;
+            (self.prefix)(pair, operand)
+        } else {
+            let mut operand = (self.primary)(pair)?;
+            while let Some(operator) = self.pairs.next_if(|pair| is_postfix_operator(pair.as_rule())) {
+                operand = (self.postfix)(operand, operator)?;
+            }
+            Ok(operand)
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Interactive shell evaluating one input at a time with the jit.
+//!
+//! Every input becomes its own module added to a single execution engine. Variables are globals of the module that
+//! defines them and functions stay callable, so later inputs declare and link against them.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::types::StructType;
+use inkwell::OptimizationLevel;
+use crate::diagnostics::{Diagnostic, SourceError, SourceFile};
+use crate::ll_code_gen::jit::flush_c_stdio;
+use crate::ll_code_gen::{CompileError, LLIRCodeGenerator};
+use crate::parser::parse_repl_input;
+use crate::typeck::{check_repl_input, Session, Type};
+
+const REPL_FILE_NAME: &str = "<repl>";
+
+/// Layout of function values, which are returned like any other struct of two pointers.
+#[repr(C)]
+struct Closure {
+    _function: *const c_void,
+    _environment: *const c_void,
+}
+
+pub struct Repl<'ctx> {
+    code_gen: LLIRCodeGenerator<'ctx>,
+    engine: ExecutionEngine<'ctx>,
+    session: Session,
+    /// Global symbols of the variables in the session.
+    symbols: HashMap<String, String>,
+    /// Llvm types of the structs in the session.
+    struct_types: HashMap<String, StructType<'ctx>>,
+    inputs: usize,
+    last_ir: Option<String>,
+}
+
+impl<'ctx> Repl<'ctx> {
+    pub fn new(context: &'ctx Context) -> Result<Self, CompileError> {
+        let engine = context.create_module("repl")
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .map_err(|error| CompileError::Jit(error.to_string()))?;
+
+        Ok(Repl {
+            code_gen: LLIRCodeGenerator::new(context),
+            engine,
+            session: Session::default(),
+            symbols: HashMap::new(),
+            struct_types: HashMap::new(),
+            inputs: 0,
+            last_ir: None,
+        })
+    }
+
+    /// Evaluates statements or function declarations, returning the value of a trailing expression for display.
+    /// Definitions of an input are only kept if it compiles.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, CompileError> {
+        let file = SourceFile::new(REPL_FILE_NAME, input);
+        let program = parse_repl_input(&file.text)
+            .map_err(|error| vec![Diagnostic::from(error)])
+            .and_then(|source| check_repl_input(source, &file.text, &self.session)
+                .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect()))
+            .map_err(|diagnostics| SourceError { file, diagnostics })?;
+
+        self.inputs += 1;
+        let name = format!("repl.{}", self.inputs);
+        let compiled = self.code_gen.create_repl_module(&name, program, &self.session, &self.symbols, &self.struct_types)?;
+        self.last_ir = Some(compiled.module.print_to_string().to_string());
+        self.engine.add_module(&compiled.module)
+            .map_err(|_| CompileError::Jit(format!("cannot add module {}", name)))?;
+
+        for (name, (variable, symbol)) in compiled.variables {
+            self.session.variables.insert(name.clone(), variable);
+            self.symbols.insert(name, symbol);
+        }
+        self.session.functions.extend(compiled.functions);
+        self.session.structs.extend(compiled.structs.into_iter().map(|structure| (structure.name.clone(), structure)));
+        self.struct_types = compiled.struct_types;
+
+        let value = self.execute(&compiled.function, &compiled.result);
+        flush_c_stdio();
+        value
+    }
+
+    /// The llvm ir generated for the last input that compiled.
+    pub fn last_ir(&self) -> Option<&str> {
+        self.last_ir.as_deref()
+    }
+
+    fn execute(&self, function: &str, result: &Type) -> Result<Option<String>, CompileError> {
+        let jit_error = |error: inkwell::execution_engine::FunctionLookupError| CompileError::Jit(error.to_string());
+        unsafe {
+            match result {
+                Type::Int => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn() -> i64>(function).map_err(jit_error)?;
+                    Ok(Some(function.call().to_string()))
+                },
+                Type::Bool => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn() -> bool>(function).map_err(jit_error)?;
+                    Ok(Some(function.call().to_string()))
+                },
+                Type::Str => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn() -> *const c_char>(function).map_err(jit_error)?;
+                    let value = CStr::from_ptr(function.call()).to_string_lossy();
+                    Ok(Some(format!("{:?}", value)))
+                },
+                Type::Function(signature) => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn() -> Closure>(function).map_err(jit_error)?;
+                    function.call();
+                    Ok(Some(format!("<{}>", signature)))
+                },
+                Type::Struct(name) => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn() -> *const u8>(function).map_err(jit_error)?;
+                    Ok(Some(self.format_struct(function.call(), name)))
+                },
+                _ => {
+                    let function = self.engine.get_function::<unsafe extern "C" fn()>(function).map_err(jit_error)?;
+                    function.call();
+                    Ok(None)
+                },
+            }
+        }
+    }
+
+    /// Formats a struct in the memory of the jit like its literal, using the field offsets of the target.
+    unsafe fn format_struct(&self, value: *const u8, name: &str) -> String {
+        let structure = &self.session.structs[name];
+        let layout = self.struct_types[name];
+        let target_data = self.engine.get_target_data();
+        let fields = structure.fields.iter()
+            .enumerate()
+            .map(|(index, (field, ty))| {
+                let offset = target_data.offset_of_element(&layout, index as u32).expect("struct has the field");
+                format!("{}: {}", field, self.format_value(value.add(offset as usize), ty))
+            })
+            .collect::<Vec<_>>();
+        format!("{} {{ {} }}", name, fields.join(", "))
+    }
+
+    unsafe fn format_value(&self, value: *const u8, ty: &Type) -> String {
+        match ty {
+            Type::Int => (value as *const i64).read().to_string(),
+            Type::Bool => (*value != 0).to_string(),
+            Type::Str => format!("{:?}", CStr::from_ptr((value as *const *const c_char).read()).to_string_lossy()),
+            Type::Struct(name) => self.format_struct(value, name),
+            other => format!("<{}>", other),
+        }
+    }
+}
+
+/// Whether the input ends inside a braced block, string or block comment, so the shell should read more lines
+/// before evaluating.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_line_comment = false;
+    let mut comment_depth = 0i32;
+    let mut chars = input.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '\n' if in_line_comment => in_line_comment = false,
+            _ if in_line_comment => {},
+            '/' if !in_string && chars.peek() == Some(&'*') => {
+                chars.next();
+                comment_depth += 1;
+            },
+            '*' if comment_depth > 0 && chars.peek() == Some(&'/') => {
+                chars.next();
+                comment_depth -= 1;
+            },
+            _ if comment_depth > 0 => {},
+            '/' if !in_string && chars.peek() == Some(&'/') => in_line_comment = true,
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => depth -= 1,
+            _ => {},
+        }
+    }
+    in_string || depth > 0 || comment_depth > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bindings_persist_across_inputs() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("x = 40").unwrap());
+        assert_eq!(None, repl.eval("name = \"chuck\"").unwrap());
+        assert_eq!(Some("42".to_string()), repl.eval("x + 2").unwrap());
+        assert_eq!(Some("\"chuck\"".to_string()), repl.eval("name").unwrap());
+        assert_eq!(Some("true".to_string()), repl.eval("x > 2 && !false").unwrap());
+        assert!(repl.last_ir().unwrap().contains("define zeroext i1"));
+        assert_eq!(Some("\"yes\"".to_string()), repl.eval("if x > 2 { \"yes\" } else { \"no\" }").unwrap());
+    }
+
+    #[test]
+    fn functions_are_callable_from_later_inputs() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("func double(n) {\n\tn * 2\n}").unwrap());
+        assert_eq!(None, repl.eval("x = double(4)").unwrap());
+        assert_eq!(Some("16".to_string()), repl.eval("double(x)").unwrap());
+    }
+
+    #[test]
+    fn variables_can_be_defined_again() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        repl.eval("x = 1").unwrap();
+        repl.eval("x = x + 1").unwrap();
+        repl.eval("x = \"two\"").unwrap();
+        assert_eq!(Some("\"two\"".to_string()), repl.eval("x").unwrap());
+    }
+
+    #[test]
+    fn mutable_variables_are_assigned_in_place() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        repl.eval("var count = 1").unwrap();
+        repl.eval("count = count + 1").unwrap();
+        assert!(!repl.last_ir().unwrap().contains("@count.repl.2"));
+        assert_eq!(Some("2".to_string()), repl.eval("count").unwrap());
+        assert!(matches!(repl.eval("count = \"two\""), Err(CompileError::Source(_))));
+    }
+
+    #[test]
+    fn externs_are_callable_from_later_inputs() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("extern func labs(n: clong) -> clong").unwrap());
+        assert_eq!(Some("7".to_string()), repl.eval("labs(-7)").unwrap());
+        assert!(repl.last_ir().unwrap().contains("declare i64 @labs(i64)"));
+    }
+
+    #[test]
+    fn c_ints_keep_their_width_in_later_inputs() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("extern func abs(n: cint) -> cint\nextern func atoi(s: str) -> cint").unwrap());
+        assert_eq!(Some("7".to_string()), repl.eval("abs(4294967289)").unwrap());
+        assert!(repl.last_ir().unwrap().contains("declare signext i32 @abs(i32 signext)"));
+        assert_eq!(Some("-42".to_string()), repl.eval("atoi(\"-42\")").unwrap());
+    }
+
+    #[test]
+    fn partially_applied_functions_are_values() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("func add(a: int, b: int) -> int { a + b }").unwrap());
+        assert_eq!(Some("<fn(int) -> int>".to_string()), repl.eval("add(40)").unwrap());
+        assert_eq!(None, repl.eval("increment = add(1)").unwrap());
+        assert_eq!(Some("42".to_string()), repl.eval("increment(41)").unwrap());
+    }
+
+    #[test]
+    fn lambdas_capture_variables_of_earlier_inputs() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("offset = 10").unwrap());
+        assert_eq!(None, repl.eval("shift = |x| x + offset").unwrap());
+        assert_eq!(Some("15".to_string()), repl.eval("shift(5)").unwrap());
+    }
+
+    #[test]
+    fn structs_are_printed_with_their_fields() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert_eq!(None, repl.eval("struct Person { name: str, age: int }").unwrap());
+        assert_eq!(None, repl.eval("var ada = Person { name: \"Ada\", age: 36 }").unwrap());
+        assert_eq!(None, repl.eval("ada.age = ada.age + 1").unwrap());
+        assert_eq!(Some("Person { name: \"Ada\", age: 37 }".to_string()), repl.eval("ada").unwrap());
+        assert_eq!(Some("37".to_string()), repl.eval("ada.age").unwrap());
+    }
+
+    #[test]
+    fn failed_inputs_keep_no_definitions() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        assert!(matches!(repl.eval("y = 1\nz = y + \"one\""), Err(CompileError::Source(_))));
+        assert!(matches!(repl.eval("y"), Err(CompileError::Source(_))));
+        assert!(matches!(repl.eval("return 1"), Err(CompileError::Source(_))));
+    }
+
+    #[test]
+    fn last_ir_is_the_module_of_the_last_input() {
+        let context = Context::create();
+        let mut repl = Repl::new(&context).unwrap();
+
+        repl.eval("x = 1").unwrap();
+        repl.eval("x + 1").unwrap();
+        let ir = repl.last_ir().unwrap();
+        assert!(ir.contains("@x.repl.1 = external global i64"));
+        assert!(ir.contains("define i64 @repl.2()"));
+    }
+
+    #[test]
+    fn it_waits_for_unclosed_blocks_and_strings() {
+        assert!(is_incomplete("func double(n) {"));
+        assert!(is_incomplete("x = \"{"));
+        assert!(!is_incomplete("x = \"{\""));
+        assert!(!is_incomplete("func double(n) {\n\tn * 2\n}"));
+    }
+
+    #[test]
+    fn braces_in_comments_are_ignored() {
+        assert!(!is_incomplete("x = 1 // {"));
+        assert!(!is_incomplete("/* \" { */ x = 1"));
+        assert!(is_incomplete("/* outer /* inner */"));
+        assert!(is_incomplete("if true { // }"));
+    }
+}
@@ -0,0 +1,29 @@
+use thiserror::Error;
+use crate::parser::Span;
+
+#[derive(Error, Debug)]
+#[error("{message} at {line}:{column}")]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+    pub help: Option<String>,
+    /// Points at a related location, e.g. the definition of an assigned variable.
+    pub note: Option<(String, Span)>,
+}
+
+impl TypeError {
+    pub fn new(message: String, span: Span, source: &str) -> Self {
+        let (line, column) = span.line_col(source);
+        TypeError { message, span, line, column, help: None, note: None }
+    }
+
+    pub fn with_help(self, help: impl Into<String>) -> Self {
+        TypeError { help: Some(help.into()), ..self }
+    }
+
+    pub fn with_note(self, message: impl Into<String>, span: Span) -> Self {
+        TypeError { note: Some((message.into(), span)), ..self }
+    }
+}
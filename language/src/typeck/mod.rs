@@ -0,0 +1,1577 @@
+//! Static type checking between parsing and code generation.
+//!
+//! Types of unannotated parameters, return values and variables are inferred by unification across the whole
+//! program, so every expression of the resulting [`TypedProgram`] carries a concrete [`Type`].
+
+pub mod errors;
+pub mod typed;
+pub mod types;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::parser::ast::{Block, Expr, ExprKind, ExternDecl, FunctionDecl, Ident, Mutability, Param, Program, ProgramKind, ReplInput, Stmt, StmtKind, StructDecl};
+use crate::parser::Span;
+
+pub use errors::TypeError;
+pub use typed::*;
+pub use types::{FunctionType, StructType, Type};
+
+/// The c `int` and `long` types, only known to extern signatures.
+const C_INT_TYPE_NAME: &str = "cint";
+const C_LONG_TYPE_NAME: &str = "clong";
+
+/// Checks the whole program and reports every error found, not just the first one.
+pub fn check_source(source: Program, input: &str) -> Result<TypedProgram, Vec<TypeError>> {
+    TypeChecker::new(input).check(source)
+}
+
+/// Checks one input of the interactive shell, which may use the definitions of earlier inputs. Statements are
+/// returned as [`Entry::Script`].
+pub fn check_repl_input(source: ReplInput, input: &str, session: &Session) -> Result<TypedProgram, Vec<TypeError>> {
+    TypeChecker::new(input).check_repl_input(source, session)
+}
+
+/// Variables, functions and structs defined by earlier inputs of an interactive session.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub variables: HashMap<String, Variable>,
+    pub functions: HashMap<String, FunctionType>,
+    pub structs: HashMap<String, StructType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub ty: Type,
+    /// Only mutable variables can be assigned after their definition.
+    pub mutable: bool,
+    /// Where the variable is defined, `None` for variables of earlier repl inputs.
+    pub span: Option<Span>,
+}
+
+impl Variable {
+    fn immutable(ty: Type, span: Span) -> Self {
+        Variable { ty, mutable: false, span: Some(span) }
+    }
+}
+
+struct TypeChecker<'s> {
+    input: &'s str,
+    /// Inferred type of every type variable, indexed by the variable id
+    substitution: RefCell<Vec<Option<Type>>>,
+    functions: HashMap<String, FunctionType>,
+    structs: HashMap<String, StructType>,
+    errors: RefCell<Vec<TypeError>>,
+}
+
+/// Variables visible at a point of a function. Blocks check their statements in a [nested](FunctionScope::nested)
+/// copy, so their variables are gone after the block.
+#[derive(Clone)]
+struct FunctionScope {
+    name: String,
+    /// Variables of each enclosing block, the innermost block last. Definitions may shadow outer blocks.
+    locals: Vec<HashMap<String, Variable>>,
+    /// Variables of earlier repl inputs, which new definitions may shadow.
+    outer: HashMap<String, Variable>,
+    enclosing: Enclosing,
+    return_type: Type,
+    body: BodyKind,
+    /// Number of loops around the checked statements, `break` and `continue` need at least one.
+    loops: usize,
+}
+
+impl FunctionScope {
+    /// A copy for checking a block inside the current one.
+    fn nested(&self) -> FunctionScope {
+        let mut scope = self.clone();
+        scope.locals.push(HashMap::new());
+        scope
+    }
+
+    /// Looks up the variable from the innermost block outwards.
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.locals.iter().rev()
+            .find_map(|block| block.get(name))
+            .or_else(|| self.outer.get(name))
+            .or_else(|| self.enclosing.capture(name))
+    }
+
+    /// Whether the variable belongs to a function around the lambda being checked.
+    fn is_captured(&self, name: &str) -> bool {
+        self.locals.iter().all(|block| !block.contains_key(name))
+            && !self.outer.contains_key(name)
+            && self.enclosing.variables.contains_key(name)
+    }
+
+    /// Every variable visible at this point, as seen by a lambda defined here.
+    fn visible(&self) -> HashMap<String, Variable> {
+        let mut variables = self.enclosing.variables.clone();
+        variables.extend(self.outer.clone());
+        for block in self.locals.iter() {
+            variables.extend(block.clone());
+        }
+        variables
+    }
+
+    /// The variable if it is defined by the current block itself.
+    fn lookup_in_block(&self, name: &str) -> Option<&Variable> {
+        self.locals.last().and_then(|block| block.get(name))
+    }
+
+    fn define(&mut self, name: String, variable: Variable) {
+        self.locals.last_mut().expect("function has a block").insert(name, variable);
+    }
+}
+
+/// Variables of the functions around a lambda. The lambda captures the ones it uses by value.
+#[derive(Clone, Default)]
+struct Enclosing {
+    variables: HashMap<String, Variable>,
+    /// Names of the used variables in the order of their first use, shared by all blocks of the lambda.
+    captured: Rc<RefCell<Vec<String>>>,
+}
+
+impl Enclosing {
+    fn capture(&self, name: &str) -> Option<&Variable> {
+        let variable = self.variables.get(name)?;
+        let mut captured = self.captured.borrow_mut();
+        if !captured.iter().any(|it| it == name) {
+            captured.push(name.to_string());
+        }
+        Some(variable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Function,
+    /// The statements of a script return an exit status instead of a value.
+    Script,
+    /// Repl inputs have nothing to return to.
+    Repl,
+}
+
+impl<'s> TypeChecker<'s> {
+    fn new(input: &'s str) -> Self {
+        TypeChecker {
+            input,
+            substitution: RefCell::new(Vec::new()),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            errors: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn check(mut self, source: Program) -> Result<TypedProgram, Vec<TypeError>> {
+        self.declare_builtins();
+        let structs = self.check_structs(source.structs);
+        let externs = self.check_externs(source.externs);
+
+        let mut program = match source.kind {
+            ProgramKind::Script(statements) => {
+                let end = Span::new(source.span.end, source.span.end);
+                let mut scope = FunctionScope {
+                    name: "main".to_string(),
+                    locals: vec![HashMap::new()],
+                    outer: HashMap::new(),
+                    enclosing: Enclosing::default(),
+                    return_type: Type::Int,
+                    body: BodyKind::Script,
+                    loops: 0,
+                };
+                let body = self.check_body(statements, &mut scope, end);
+                TypedProgram { externs: Vec::new(), structs: Vec::new(), functions: Vec::new(), entry: Entry::Script(body) }
+            },
+            ProgramKind::Functions { entry, functions } => self.check_program(entry, functions),
+        };
+        program.externs = externs;
+        program.structs = structs;
+        self.finish(program)
+    }
+
+    fn check_repl_input(mut self, source: ReplInput, session: &Session) -> Result<TypedProgram, Vec<TypeError>> {
+        self.declare_builtins();
+        self.functions.extend(session.functions.clone());
+        self.structs.extend(session.structs.clone());
+
+        let program = match source {
+            ReplInput::Functions { externs, structs, functions } => {
+                let structs = self.check_structs(structs);
+                let externs = self.check_externs(externs);
+                let functions = self.check_functions(functions);
+                TypedProgram { externs, structs, functions, entry: Entry::Script(Vec::new()) }
+            },
+            ReplInput::Statements { externs, structs, statements } => {
+                let structs = self.check_structs(structs);
+                let externs = self.check_externs(externs);
+                let mut scope = FunctionScope {
+                    name: "repl".to_string(),
+                    locals: vec![HashMap::new()],
+                    outer: session.variables.clone(),
+                    enclosing: Enclosing::default(),
+                    return_type: Type::Unit,
+                    body: BodyKind::Repl,
+                    loops: 0,
+                };
+                let body = self.check_body(statements, &mut scope, Span::default());
+                TypedProgram { externs, structs, functions: Vec::new(), entry: Entry::Script(body) }
+            },
+        };
+        self.finish(program)
+    }
+
+    fn finish(self, program: TypedProgram) -> Result<TypedProgram, Vec<TypeError>> {
+        // types are only complete if checking succeeded, resolving them earlier just repeats the errors
+        if self.errors.borrow().is_empty() {
+            let program = self.finalize(program);
+            if self.errors.borrow().is_empty() {
+                return Ok(program);
+            }
+        }
+        Err(self.errors.into_inner())
+    }
+
+    fn declare_builtins(&mut self) {
+        self.functions.insert("printf".to_string(), FunctionType {
+            params: vec![Type::Str],
+            variadic: true,
+            return_type: Box::new(Type::Unit),
+        });
+    }
+
+    fn check_program(&mut self, entry: Ident, declarations: Vec<FunctionDecl>) -> TypedProgram {
+        let functions = self.check_functions(declarations);
+
+        match self.functions.get(&entry.name) {
+            None => self.report(self.error(format!("entry function '{}' is not defined", entry.name), entry.span)
+                .with_help(format!("declare it with `func {}() {{ ... }}`", entry.name))),
+            Some(function) if !function.params.is_empty() => {
+                self.report(self.error(format!("entry function '{}' must not take parameters", entry.name), entry.span))
+            },
+            Some(_) => {},
+        }
+
+        TypedProgram { externs: Vec::new(), structs: Vec::new(), functions, entry: Entry::Function(entry.name) }
+    }
+
+    /// Declares all structs before resolving their fields, so a field can have the type of any struct.
+    fn check_structs(&mut self, declarations: Vec<StructDecl>) -> Vec<StructType> {
+        let mut declared = Vec::new();
+        for declaration in declarations {
+            let name = &declaration.name;
+            if Type::from_type_name(&name.name).is_some() || [C_INT_TYPE_NAME, C_LONG_TYPE_NAME].contains(&name.name.as_str()) {
+                self.report(self.error(format!("struct '{}' has the name of a builtin type", name.name), name.span));
+                continue;
+            }
+            if self.structs.contains_key(&name.name) {
+                self.report(self.error(format!("struct '{}' is already defined", name.name), name.span));
+                continue;
+            }
+            self.structs.insert(name.name.clone(), StructType { name: name.name.clone(), fields: Vec::new() });
+            declared.push(declaration);
+        }
+
+        let mut structs = Vec::new();
+        for declaration in &declared {
+            let mut fields: Vec<(String, Type)> = Vec::new();
+            for field in &declaration.fields {
+                if fields.iter().any(|(name, _)| *name == field.name.name) {
+                    self.report(self.error(format!("field '{}' is already defined", field.name.name), field.span));
+                    continue;
+                }
+                let ty = field.type_name.as_ref().map_or(Type::Error, |type_name| self.annotated_type(type_name));
+                fields.push((field.name.name.clone(), ty));
+            }
+            structs.push(StructType { name: declaration.name.name.clone(), fields });
+        }
+        for structure in &structs {
+            self.structs.insert(structure.name.clone(), structure.clone());
+        }
+
+        for (declaration, structure) in declared.iter().zip(&structs) {
+            if self.contains_struct(structure, &structure.name, &mut Vec::new()) {
+                self.report(self.error(format!(
+                    "struct '{}' contains itself, so its size is infinite", structure.name
+                ), declaration.name.span));
+            }
+        }
+        structs
+    }
+
+    /// Whether a field of the struct, or of the structs in its fields, has the struct `name`.
+    fn contains_struct(&self, structure: &StructType, name: &str, visited: &mut Vec<String>) -> bool {
+        structure.fields.iter().any(|(_, ty)| match ty {
+            Type::Struct(field) if field == name => true,
+            Type::Struct(field) if !visited.contains(field) => {
+                visited.push(field.clone());
+                self.contains_struct(&self.structs[field], name, visited)
+            },
+            _ => false,
+        })
+    }
+
+    /// Declares the signatures of foreign functions. Their parameters are annotated, a missing return type is unit.
+    fn check_externs(&mut self, declarations: Vec<ExternDecl>) -> Vec<TypedExtern> {
+        let mut externs = Vec::new();
+        for declaration in declarations {
+            let name = declaration.name;
+            if self.functions.contains_key(&name.name) {
+                self.report(self.error(format!("function '{}' is already defined", name.name), name.span));
+                continue;
+            }
+
+            let mut params = Vec::new();
+            for (position, param) in declaration.params.iter().enumerate() {
+                if declaration.params[..position].iter().any(|it| it.name.name == param.name.name) {
+                    self.report(self.error(format!("parameter '{}' is already defined", param.name.name), param.span));
+                }
+                let type_name = param.type_name.as_ref().expect("extern parameters are typed");
+                params.push(self.extern_type(type_name));
+            }
+            let return_type = declaration.return_type
+                .map_or(ExternType::Value(Type::Unit), |type_name| self.extern_type(&type_name));
+
+            let external = TypedExtern { name: name.name, params, variadic: declaration.variadic, return_type };
+            self.functions.insert(external.name.clone(), external.signature());
+            externs.push(external);
+        }
+        externs
+    }
+
+    /// Types of extern signatures. Integers name their c type, since the width of an `int` differs between c and
+    /// the program. Structs are not passed like c passes them.
+    fn extern_type(&self, type_name: &Ident) -> ExternType {
+        match type_name.name.as_str() {
+            C_INT_TYPE_NAME => ExternType::CInt,
+            C_LONG_TYPE_NAME => ExternType::CLong,
+            "int" => {
+                self.report(self.error("an `int` of the program is not a c `int`".to_string(), type_name.span)
+                    .with_help("declare a c `int` as `cint` and a c `long` as `clong`"));
+                ExternType::Value(Type::Error)
+            },
+            _ => match self.annotated_type(type_name) {
+                Type::Struct(name) => {
+                    self.report(self.error(format!("struct '{}' cannot be passed to or from c functions", name), type_name.span)
+                        .with_help("pass its fields as separate values"));
+                    ExternType::Value(Type::Error)
+                },
+                ty => ExternType::Value(ty),
+            },
+        }
+    }
+
+    /// Declares all functions before checking their bodies, so they can call each other in any order.
+    fn check_functions(&mut self, declarations: Vec<FunctionDecl>) -> Vec<TypedFunction> {
+        let declared = declarations.into_iter()
+            .filter_map(|declaration| {
+                let params = self.declare_function(&declaration)?;
+                Some((declaration, params))
+            })
+            .collect::<Vec<_>>();
+
+        declared.into_iter()
+            .map(|(declaration, params)| self.check_function(declaration, params))
+            .collect()
+    }
+
+    /// Declares the signature of a function, `None` if the name is already taken.
+    fn declare_function(&mut self, declaration: &FunctionDecl) -> Option<Vec<TypedParam>> {
+        let name = &declaration.name;
+        if self.functions.contains_key(&name.name) {
+            self.report(self.error(format!("function '{}' is already defined", name.name), name.span));
+            return None;
+        }
+
+        let mut params = Vec::new();
+        for param in &declaration.params {
+            let param = self.declare_parameter(param, &params);
+            params.push(param);
+        }
+        let return_type = match &declaration.return_type {
+            Some(type_name) => self.annotated_type(type_name),
+            None => self.fresh_var(),
+        };
+
+        self.functions.insert(name.name.clone(), FunctionType {
+            params: params.iter().map(|param| param.ty.clone()).collect(),
+            variadic: false,
+            return_type: Box::new(return_type),
+        });
+        Some(params)
+    }
+
+    fn declare_parameter(&self, param: &Param, previous: &[TypedParam]) -> TypedParam {
+        if previous.iter().any(|it| it.name == param.name.name) {
+            self.report(self.error(format!("parameter '{}' is already defined", param.name.name), param.span));
+        }
+        let ty = match &param.type_name {
+            Some(type_name) => self.annotated_type(type_name),
+            None => self.fresh_var(),
+        };
+        TypedParam { name: param.name.name.clone(), ty, span: param.span }
+    }
+
+    fn annotated_type(&self, type_name: &Ident) -> Type {
+        if self.structs.contains_key(&type_name.name) {
+            return Type::Struct(type_name.name.clone());
+        }
+        Type::from_type_name(&type_name.name).unwrap_or_else(|| {
+            let help = match type_name.name.as_str() {
+                C_INT_TYPE_NAME | C_LONG_TYPE_NAME => "`cint` and `clong` are only available in the signatures of externs",
+                _ => "available types are `str`, `int`, `bool` and declared structs",
+            };
+            self.report(self.error(format!("unknown type '{}'", type_name.name), type_name.span).with_help(help));
+            Type::Error
+        })
+    }
+
+    fn check_function(&mut self, declaration: FunctionDecl, params: Vec<TypedParam>) -> TypedFunction {
+        let signature = self.functions[&declaration.name.name].clone();
+        let mut scope = FunctionScope {
+            name: declaration.name.name.clone(),
+            // the body is a block inside the parameters, so its definitions may shadow them
+            locals: vec![
+                params.iter().map(|param| (param.name.clone(), Variable::immutable(param.ty.clone(), param.span))).collect(),
+                HashMap::new(),
+            ],
+            outer: HashMap::new(),
+            enclosing: Enclosing::default(),
+            return_type: *signature.return_type.clone(),
+            body: BodyKind::Function,
+            loops: 0,
+        };
+        let end = declaration.body_span.end;
+        let body = self.check_body(declaration.body, &mut scope, Span::new(end - 1, end));
+
+        TypedFunction {
+            name: declaration.name.name,
+            params,
+            return_type: *signature.return_type,
+            body,
+            span: declaration.span,
+        }
+    }
+
+    /// Checks the statements of a function body. `end` locates the end of the body for missing return values.
+    fn check_body(&self, statements: Vec<Stmt>, scope: &mut FunctionScope, end: Span) -> Vec<TypedStmt> {
+        let mut body = self.check_statements(statements, scope);
+        let returned = body.iter().any(TypedStmt::diverges);
+        if !returned && scope.body == BodyKind::Function {
+            self.check_implicit_return(&mut body, scope, end);
+        }
+        body
+    }
+
+    fn check_statements(&self, statements: Vec<Stmt>, scope: &mut FunctionScope) -> Vec<TypedStmt> {
+        let mut checked = Vec::new();
+        let mut interrupted = None;
+        let mut unreachable = false;
+        for statement in statements {
+            let span = statement.span;
+            if let (Some(interruption), false) = (interrupted, unreachable) {
+                self.report(self.error(format!("unreachable statement after {}", interruption), span));
+                unreachable = true;
+            }
+
+            let kind = match statement.kind {
+                StmtKind::Definition { mutability, target, value } => self.check_definition(mutability, target, value, scope),
+                StmtKind::Assignment { target, value } => self.check_assignment(target, value, scope),
+                StmtKind::FieldAssignment { target, fields, value } => self.check_field_assignment(target, fields, value, scope),
+                StmtKind::Expression(expression) => TypedStmtKind::Expression(self.check_expression(expression, scope)),
+                StmtKind::Return(expression) => self.check_return(expression, span, scope),
+                StmtKind::While { condition, body } => self.check_while(condition, body, scope),
+                StmtKind::For { variable, start, end, body } => self.check_for(variable, start, end, body, scope),
+                StmtKind::Block(block) => TypedStmtKind::Block(self.check_block(block, scope).0),
+                StmtKind::Break => self.check_loop_jump(TypedStmtKind::Break, "break", span, scope),
+                StmtKind::Continue => self.check_loop_jump(TypedStmtKind::Continue, "continue", span, scope),
+            };
+            let statement = TypedStmt { kind, span };
+            if interrupted.is_none() && statement.interrupts() {
+                interrupted = Some(if statement.diverges() { "return" } else { "break or continue" });
+            }
+            checked.push(statement);
+        }
+        checked
+    }
+
+    /// Checks the block in its own scope. Returns the type of its value, `None` if control never reaches its end.
+    fn check_block(&self, block: Block, scope: &FunctionScope) -> (TypedBlock, Option<Type>) {
+        let mut scope = scope.nested();
+        let statements = self.check_statements(block.statements, &mut scope);
+        let block = TypedBlock { statements };
+        if block.interrupts() {
+            return (block, None);
+        }
+        let ty = match block.statements.last() {
+            Some(TypedStmt { kind: TypedStmtKind::Expression(value), .. }) => value.ty.clone(),
+            _ => Type::Unit,
+        };
+        (block, Some(ty))
+    }
+
+    /// A trailing expression is the return value of a function, otherwise it returns unit.
+    fn check_implicit_return(&self, body: &mut Vec<TypedStmt>, scope: &FunctionScope, end: Span) {
+        match body.pop() {
+            Some(TypedStmt { kind: TypedStmtKind::Expression(value), span }) => {
+                if !self.unify(&scope.return_type, &value.ty) {
+                    self.report(self.error(format!(
+                        "function '{}' returns {}, but its last expression is {}",
+                        scope.name, self.resolve(&scope.return_type), self.resolve(&value.ty)
+                    ), value.span));
+                }
+                body.push(TypedStmt { kind: TypedStmtKind::Return(Some(value)), span });
+            },
+            last => {
+                body.extend(last);
+                if !self.unify(&scope.return_type, &Type::Unit) {
+                    self.report(self.error(format!(
+                        "function '{}' must end with a return value of type {}",
+                        scope.name, self.resolve(&scope.return_type)
+                    ), end));
+                }
+            },
+        }
+    }
+
+    fn check_return(&self, expression: Option<Expr>, span: Span, scope: &FunctionScope) -> TypedStmtKind {
+        let value = expression.map(|it| self.check_expression(it, scope));
+        let (found, span) = value.as_ref().map_or((Type::Unit, span), |value| (value.ty.clone(), value.span));
+
+        match scope.body {
+            BodyKind::Script => if value.is_some() && !self.unify(&Type::Int, &found) {
+                self.report(self.error(format!("exit status must be int, found {}", self.resolve(&found)), span));
+            },
+            BodyKind::Repl => self.report(self.error("`return` outside of a function".to_string(), span)
+                .with_help("the value of a trailing expression is printed")),
+            BodyKind::Function => if !self.unify(&scope.return_type, &found) {
+                self.report(self.error(format!(
+                    "function '{}' returns {}, found {}",
+                    scope.name, self.resolve(&scope.return_type), self.resolve(&found)
+                ), span));
+            },
+        }
+
+        TypedStmtKind::Return(value)
+    }
+
+    fn check_while(&self, condition: Expr, body: Block, scope: &FunctionScope) -> TypedStmtKind {
+        let condition = self.check_expression(condition, scope);
+        if !self.unify(&Type::Bool, &condition.ty) {
+            self.report(self.error(format!(
+                "condition of `while` must be bool, found {}", self.resolve(&condition.ty)
+            ), condition.span));
+        }
+
+        let body = self.check_loop_body(body, None, scope);
+        TypedStmtKind::While { condition, body }
+    }
+
+    fn check_for(&self, variable: Ident, start: Expr, end: Expr, body: Block, scope: &FunctionScope) -> TypedStmtKind {
+        let start = self.check_expression(start, scope);
+        let end = self.check_expression(end, scope);
+        for bound in [&start, &end] {
+            if !self.unify(&Type::Int, &bound.ty) {
+                self.report(self.error(format!(
+                    "bounds of a range must be int, found {}", self.resolve(&bound.ty)
+                ), bound.span));
+            }
+        }
+        self.check_new_variable(&variable, scope);
+
+        let body = self.check_loop_body(body, Some(&variable), scope);
+        TypedStmtKind::For { variable: variable.name, start, end, body }
+    }
+
+    /// Checks the body of a loop, `variable` is the int counter of a `for` loop.
+    fn check_loop_body(&self, body: Block, variable: Option<&Ident>, scope: &FunctionScope) -> TypedBlock {
+        let mut scope = scope.nested();
+        scope.loops += 1;
+        if let Some(variable) = variable {
+            scope.define(variable.name.clone(), Variable::immutable(Type::Int, variable.span));
+        }
+        let (body, _) = self.check_block(body, &scope);
+        body
+    }
+
+    fn check_loop_jump(&self, kind: TypedStmtKind, keyword: &str, span: Span, scope: &FunctionScope) -> TypedStmtKind {
+        if scope.loops == 0 {
+            self.report(self.error(format!("`{}` outside of a loop", keyword), span));
+        }
+        kind
+    }
+
+    fn check_definition(&self, mutability: Mutability, target: Ident, value: Expr, scope: &mut FunctionScope) -> TypedStmtKind {
+        self.check_new_variable(&target, scope);
+        self.define(target, mutability == Mutability::Mutable, value, scope)
+    }
+
+    /// Assigns a mutable variable. Unknown names and immutable variables of earlier repl inputs are defined instead.
+    fn check_assignment(&self, target: Ident, value: Expr, scope: &mut FunctionScope) -> TypedStmtKind {
+        if scope.is_captured(&target.name) {
+            self.report(self.error(format!("cannot assign to captured variable '{}'", target.name), target.span)
+                .with_help("lambdas capture variables by value, define a new variable with `let` instead"));
+            let value = self.check_expression(value, scope);
+            return TypedStmtKind::Assign { name: target.name, value };
+        }
+        let variable = scope.lookup(&target.name).cloned();
+        match variable {
+            Some(variable) if variable.mutable => {
+                let value = self.check_expression(value, scope);
+                if !self.unify(&variable.ty, &value.ty) {
+                    self.report(self.error(format!(
+                        "cannot assign {} to variable '{}' of type {}",
+                        self.resolve(&value.ty), target.name, self.resolve(&variable.ty)
+                    ), value.span));
+                }
+                TypedStmtKind::Assign { name: target.name, value }
+            },
+            Some(Variable { span: Some(defined), .. }) => {
+                self.report(self.error(format!("cannot assign twice to immutable variable '{}'", target.name), target.span)
+                    .with_note(format!("'{}' is defined here", target.name), defined)
+                    .with_help(format!("define it with `var {} = ...` to make it mutable", target.name)));
+                let value = self.check_expression(value, scope);
+                TypedStmtKind::Assign { name: target.name, value }
+            },
+            _ => self.define(target, false, value, scope),
+        }
+    }
+
+    /// Assigns a field of a mutable struct variable, the struct value is changed in place.
+    fn check_field_assignment(&self, target: Ident, fields: Vec<Ident>, value: Expr, scope: &FunctionScope) -> TypedStmtKind {
+        let value = self.check_expression(value, scope);
+        let path = self.check_field_target(&target, &fields, &value, scope);
+        TypedStmtKind::AssignField { name: target.name, path, value }
+    }
+
+    /// The field indices from the assigned variable to the assigned field.
+    fn check_field_target(&self, target: &Ident, fields: &[Ident], value: &TypedExpr, scope: &FunctionScope) -> Vec<usize> {
+
+        if scope.is_captured(&target.name) {
+            self.report(self.error(format!("cannot assign to field of captured variable '{}'", target.name), target.span)
+                .with_help("lambdas capture variables by value, define a new variable with `let` instead"));
+            return Vec::new();
+        }
+        let variable = match scope.lookup(&target.name) {
+            Some(variable) => variable.clone(),
+            None => {
+                self.report(self.error(format!("usage of unknown variable '{}'", target.name), target.span)
+                    .with_help(format!("assign a value to '{}' before using it", target.name)));
+                return Vec::new();
+            },
+        };
+        if !variable.mutable {
+            let error = self.error(format!("cannot assign to field of immutable variable '{}'", target.name), target.span)
+                .with_help(format!("define it with `var {} = ...` to make it mutable", target.name));
+            self.report(match variable.span {
+                Some(defined) => error.with_note(format!("'{}' is defined here", target.name), defined),
+                None => error,
+            });
+        }
+
+        let mut path = Vec::new();
+        let mut ty = variable.ty;
+        let mut span = target.span;
+        for field in fields {
+            match self.lookup_field(&ty, field, span) {
+                Some((index, field_type)) => {
+                    path.push(index);
+                    ty = field_type;
+                    span = span.to(field.span);
+                },
+                None => return path,
+            }
+        }
+        if !self.unify(&ty, &value.ty) {
+            let field = fields.last().expect("field assignment has a field");
+            self.report(self.error(format!(
+                "cannot assign {} to field '{}' of type {}", self.resolve(&value.ty), field.name, self.resolve(&ty)
+            ), value.span));
+        }
+        path
+    }
+
+    /// Reports a variable defined twice in the same block, shadowing is only allowed for outer blocks.
+    fn check_new_variable(&self, target: &Ident, scope: &FunctionScope) {
+        if let Some(variable) = scope.lookup_in_block(&target.name) {
+            let error = self.error(format!("variable '{}' is already defined", target.name), target.span);
+            self.report(match variable.span {
+                Some(defined) => error.with_note(format!("'{}' is first defined here", target.name), defined),
+                None => error,
+            });
+        }
+    }
+
+    fn define(&self, target: Ident, mutable: bool, value: Expr, scope: &mut FunctionScope) -> TypedStmtKind {
+        let value = self.check_expression(value, scope);
+        let variable = Variable { ty: value.ty.clone(), mutable, span: Some(target.span) };
+        scope.define(target.name.clone(), variable);
+        TypedStmtKind::Define { name: target.name, mutable, value }
+    }
+
+    /// Checks the expression, reporting errors and continuing with [`Type::Error`] where no type can be given.
+    fn check_expression(&self, expression: Expr, scope: &FunctionScope) -> TypedExpr {
+        let span = expression.span;
+        match expression.kind {
+            ExprKind::Literal(literal) => {
+                let ty = match literal {
+                    Literal::Str(_) => Type::Str,
+                    Literal::Int(_) => Type::Int,
+                    Literal::Bool(_) => Type::Bool,
+                };
+                TypedExpr { kind: TypedExprKind::Literal(literal), ty, span }
+            },
+            ExprKind::Ident(ident) => {
+                if let Some(variable) = scope.lookup(&ident.name) {
+                    return TypedExpr { kind: TypedExprKind::Variable(ident.name), ty: variable.ty.clone(), span };
+                }
+                if let Some(signature) = self.functions.get(&ident.name) {
+                    return self.check_function_reference(ident, signature);
+                }
+                self.report(self.error(format!("usage of unknown variable '{}'", ident.name), span)
+                    .with_help(format!("assign a value to '{}' before using it", ident.name)));
+                TypedExpr { kind: TypedExprKind::Variable(ident.name), ty: Type::Error, span }
+            },
+            ExprKind::Call { callee, args } => self.check_call(callee, args, span, scope),
+            ExprKind::Apply { callee, args } => self.check_apply(*callee, args, span, scope),
+            ExprKind::Unary { op, operand } => {
+                let operand = self.check_expression(*operand, scope);
+                let ty = match op {
+                    UnaryOp::Negate => Type::Int,
+                    UnaryOp::Not => Type::Bool,
+                };
+                self.expect_operand(op.symbol(), &ty, &operand);
+                TypedExpr { kind: TypedExprKind::Unary { op, operand: Box::new(operand) }, ty, span }
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.check_expression(*lhs, scope);
+                let rhs = self.check_expression(*rhs, scope);
+                let ty = self.check_binary_operands(op, &lhs, &rhs);
+                TypedExpr { kind: TypedExprKind::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }, ty, span }
+            },
+            ExprKind::If { condition, then_block, else_block } => self.check_if(*condition, then_block, else_block, span, scope),
+            ExprKind::Lambda { params, return_type, body } => self.check_lambda(params, return_type, body, span, scope),
+            ExprKind::StructLiteral { name, fields } => self.check_struct_literal(name, fields, span, scope),
+            ExprKind::Field { value, field } => {
+                let value = self.check_expression(*value, scope);
+                let (index, ty) = self.lookup_field(&value.ty, &field, value.span).unwrap_or((0, Type::Error));
+                TypedExpr { kind: TypedExprKind::Field { value: Box::new(value), index }, ty, span }
+            },
+        }
+    }
+
+    /// Checks that the literal initializes every field of the struct exactly once.
+    fn check_struct_literal(&self, name: Ident, fields: Vec<(Ident, Expr)>, span: Span, scope: &FunctionScope) -> TypedExpr {
+        let values = fields.into_iter()
+            .map(|(field, value)| (field, self.check_expression(value, scope)))
+            .collect::<Vec<_>>();
+        let structure = match self.structs.get(&name.name) {
+            Some(structure) => structure,
+            None => {
+                self.report(self.error(format!("unknown struct '{}'", name.name), name.span)
+                    .with_help(format!("declare it with `struct {} {{ ... }}`", name.name)));
+                return TypedExpr { kind: TypedExprKind::StructLiteral { name: name.name, fields: Vec::new() }, ty: Type::Error, span };
+            },
+        };
+
+        let mut initialized: Vec<(usize, TypedExpr)> = Vec::new();
+        for (field, value) in values {
+            match structure.field(&field.name) {
+                None => self.report(self.error(format!("struct '{}' has no field '{}'", name.name, field.name), field.span)),
+                Some((index, _)) if initialized.iter().any(|(it, _)| *it == index) => {
+                    self.report(self.error(format!("field '{}' is initialized twice", field.name), field.span))
+                },
+                Some((index, ty)) => {
+                    if !self.unify(ty, &value.ty) {
+                        self.report(self.error(format!(
+                            "field '{}' of '{}' expects {}, found {}", field.name, name.name, ty, self.resolve(&value.ty)
+                        ), value.span));
+                    }
+                    initialized.push((index, value));
+                },
+            }
+        }
+
+        let missing = structure.fields.iter()
+            .enumerate()
+            .filter(|(index, _)| initialized.iter().all(|(it, _)| it != index))
+            .map(|(_, (field, _))| format!("'{}'", field))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            self.report(self.error(format!(
+                "struct literal of '{}' is missing {} {}",
+                name.name, if missing.len() == 1 { "field" } else { "fields" }, missing.join(", ")
+            ), name.span));
+        }
+
+        let kind = TypedExprKind::StructLiteral { name: name.name.clone(), fields: initialized };
+        TypedExpr { kind, ty: Type::Struct(name.name), span }
+    }
+
+    /// Index and type of a field of a struct value, `span` locates the value for errors.
+    fn lookup_field(&self, ty: &Type, field: &Ident, span: Span) -> Option<(usize, Type)> {
+        match self.shallow_resolve(ty) {
+            Type::Struct(name) => {
+                let found = self.structs[&name].field(&field.name)
+                    .map(|(index, ty)| (index, ty.clone()));
+                if found.is_none() {
+                    self.report(self.error(format!("struct '{}' has no field '{}'", name, field.name), field.span));
+                }
+                found
+            },
+            Type::Error => None,
+            Type::Var(_) => {
+                self.report(self.error(format!("cannot access field '{}' of a value of unknown type", field.name), span)
+                    .with_help("add a type annotation naming the struct"));
+                None
+            },
+            other => {
+                self.report(self.error(format!("a value of type {} has no field '{}'", other, field.name), span));
+                None
+            },
+        }
+    }
+
+    /// Checks the operands and returns the type of the operation.
+    fn check_binary_operands(&self, op: BinaryOp, lhs: &TypedExpr, rhs: &TypedExpr) -> Type {
+        match op {
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                if !self.unify(&lhs.ty, &rhs.ty) {
+                    self.report(self.error(format!(
+                        "operator '{}' expects operands of the same type, found {} and {}",
+                        op.symbol(), self.resolve(&lhs.ty), self.resolve(&rhs.ty)
+                    ), lhs.span.to(rhs.span)));
+                } else if matches!(self.resolve(&lhs.ty), Type::Str | Type::Unit | Type::Function(_) | Type::Struct(_)) {
+                    self.report(self.error(format!(
+                        "operator '{}' cannot compare values of type {}", op.symbol(), self.resolve(&lhs.ty)
+                    ), lhs.span.to(rhs.span)).with_help("only int and bool values can be compared"));
+                }
+                Type::Bool
+            },
+            BinaryOp::And | BinaryOp::Or => {
+                self.expect_operand(op.symbol(), &Type::Bool, lhs);
+                self.expect_operand(op.symbol(), &Type::Bool, rhs);
+                Type::Bool
+            },
+            _ => {
+                self.expect_operand(op.symbol(), &Type::Int, lhs);
+                self.expect_operand(op.symbol(), &Type::Int, rhs);
+                if op.is_comparison() { Type::Bool } else { Type::Int }
+            },
+        }
+    }
+
+    fn check_if(&self, condition: Expr, then_block: Block, else_block: Option<Block>, span: Span, scope: &FunctionScope) -> TypedExpr {
+        let condition = self.check_expression(condition, scope);
+        if !self.unify(&Type::Bool, &condition.ty) {
+            self.report(self.error(format!(
+                "condition of `if` must be bool, found {}", self.resolve(&condition.ty)
+            ), condition.span));
+        }
+
+        let (then_block, then_type) = self.check_block(then_block, scope);
+        let (else_block, ty) = match else_block {
+            // without else there is no value for a false condition
+            None => (None, Type::Unit),
+            Some(else_block) => {
+                let (else_block, else_type) = self.check_block(else_block, scope);
+                let ty = match (then_type, else_type) {
+                    (Some(then_type), Some(else_type)) => {
+                        if !self.unify(&then_type, &else_type) {
+                            self.report(self.error(format!(
+                                "`if` and `else` have incompatible types, {} and {}",
+                                self.resolve(&then_type), self.resolve(&else_type)
+                            ), span));
+                        }
+                        then_type
+                    },
+                    (Some(ty), None) | (None, Some(ty)) => ty,
+                    (None, None) => Type::Unit,
+                };
+                (Some(else_block), ty)
+            },
+        };
+
+        let kind = TypedExprKind::If { condition: Box::new(condition), then_block, else_block };
+        TypedExpr { kind, ty, span }
+    }
+
+    /// Checks the body of a lambda in its own function scope, which sees the variables of the enclosing scope.
+    fn check_lambda(&self, params: Vec<Param>, return_type: Option<Ident>, body: Block, span: Span, scope: &FunctionScope) -> TypedExpr {
+        let mut declared = Vec::new();
+        for param in &params {
+            let param = self.declare_parameter(param, &declared);
+            declared.push(param);
+        }
+        let return_type = match &return_type {
+            Some(type_name) => self.annotated_type(type_name),
+            None => self.fresh_var(),
+        };
+
+        let mut lambda_scope = FunctionScope {
+            name: "lambda".to_string(),
+            locals: vec![
+                declared.iter().map(|param| (param.name.clone(), Variable::immutable(param.ty.clone(), param.span))).collect(),
+                HashMap::new(),
+            ],
+            outer: HashMap::new(),
+            enclosing: Enclosing { variables: scope.visible(), captured: Rc::default() },
+            return_type: return_type.clone(),
+            body: BodyKind::Function,
+            loops: 0,
+        };
+        let end = body.span.end;
+        let body = self.check_body(body.statements, &mut lambda_scope, Span::new(end - 1, end));
+
+        // a lambda inside another lambda captures through it
+        let captures = lambda_scope.enclosing.captured.borrow().clone();
+        for name in captures.iter() {
+            scope.lookup(name);
+        }
+
+        let ty = Type::Function(FunctionType {
+            params: declared.iter().map(|param| param.ty.clone()).collect(),
+            variadic: false,
+            return_type: Box::new(return_type.clone()),
+        });
+        let kind = TypedExprKind::Lambda { params: declared, return_type, body, captures };
+        TypedExpr { kind, ty, span }
+    }
+
+    /// A declared function used as a value, variadic functions have no function type.
+    fn check_function_reference(&self, function: Ident, signature: &FunctionType) -> TypedExpr {
+        let ty = if signature.variadic {
+            self.report(self.error(format!("variadic function '{}' cannot be used as a value", function.name), function.span));
+            Type::Error
+        } else {
+            Type::Function(signature.clone())
+        };
+        TypedExpr { kind: TypedExprKind::Function(function.name), ty, span: function.span }
+    }
+
+    /// Checks a call of a declared function or of a variable holding a function value. Calls with fewer arguments
+    /// than parameters are partial applications.
+    fn check_call(&self, callee: Ident, args: Vec<Expr>, span: Span, scope: &FunctionScope) -> TypedExpr {
+        let args = args.into_iter()
+            .map(|arg| self.check_expression(arg, scope))
+            .collect::<Vec<TypedExpr>>();
+
+        if let Some(variable) = scope.lookup(&callee.name) {
+            let callee = TypedExpr { kind: TypedExprKind::Variable(callee.name), ty: variable.ty.clone(), span: callee.span };
+            return self.check_value_call(callee, args, span);
+        }
+
+        let Ident { name, span: callee_span } = callee;
+        let signature = match self.functions.get(&name) {
+            Some(signature) => signature,
+            None => {
+                self.report(self.error(format!("unknown function '{}'", name), callee_span)
+                    .with_help(format!("declare it with `func {}(...) {{ ... }}`", name)));
+                return TypedExpr { kind: TypedExprKind::Call { function: name, args }, ty: Type::Error, span };
+            },
+        };
+
+        if self.is_partial(signature, args.len()) {
+            let callee = self.check_function_reference(Ident { name, span: callee_span }, signature);
+            return self.check_value_call(callee, args, span);
+        }
+
+        let arity_matches = if signature.variadic {
+            args.len() >= signature.params.len()
+        } else {
+            args.len() == signature.params.len()
+        };
+        if !arity_matches {
+            self.report(self.error(format!(
+                "'{}' expects {} arguments, but {} were given", name, signature.params.len(), args.len()
+            ), span));
+        }
+        for (position, (param, arg)) in signature.params.iter().zip(&args).enumerate() {
+            if !self.unify(param, &arg.ty) {
+                self.report(self.error(format!(
+                    "argument {} of '{}' expects {}, found {}", position + 1, name, self.resolve(param), self.resolve(&arg.ty)
+                ), arg.span));
+            }
+        }
+
+        TypedExpr {
+            kind: TypedExprKind::Call { function: name, args },
+            ty: *signature.return_type.clone(),
+            span,
+        }
+    }
+
+    /// Calls a function value. The type of a variable not known to be a function yet is inferred from the call.
+    fn check_value_call(&self, callee: TypedExpr, args: Vec<TypedExpr>, span: Span) -> TypedExpr {
+        let name = match &callee.kind {
+            TypedExprKind::Variable(name) | TypedExprKind::Function(name) => format!("'{}'", name),
+            _ => "the function".to_string(),
+        };
+        let signature = match self.shallow_resolve(&callee.ty) {
+            Type::Function(signature) => signature,
+            Type::Var(_) => {
+                let signature = FunctionType {
+                    params: args.iter().map(|arg| arg.ty.clone()).collect(),
+                    variadic: false,
+                    return_type: Box::new(self.fresh_var()),
+                };
+                if !self.unify(&callee.ty, &Type::Function(signature.clone())) {
+                    self.report(self.error(format!(
+                        "cannot infer the type of {}, a function taking or returning itself", name
+                    ), callee.span));
+                }
+                signature
+            },
+            Type::Error => return TypedExpr { kind: TypedExprKind::Apply { callee: Box::new(callee), args }, ty: Type::Error, span },
+            other => {
+                let message = match &callee.kind {
+                    TypedExprKind::Variable(_) => format!("{} is not a function, but a variable of type {}", name, other),
+                    _ => format!("a value of type {} is not a function", other),
+                };
+                self.report(self.error(message, callee.span));
+                return TypedExpr { kind: TypedExprKind::Apply { callee: Box::new(callee), args }, ty: Type::Error, span };
+            },
+        };
+
+        if args.len() > signature.params.len() || args.is_empty() && !signature.params.is_empty() {
+            self.report(self.error(format!(
+                "{} expects {} arguments, but {} were given", name, signature.params.len(), args.len()
+            ), span));
+        }
+        for (position, (param, arg)) in signature.params.iter().zip(&args).enumerate() {
+            if !self.unify(param, &arg.ty) {
+                self.report(self.error(format!(
+                    "argument {} of {} expects {}, found {}", position + 1, name, self.resolve(param), self.resolve(&arg.ty)
+                ), arg.span));
+            }
+        }
+
+        if self.is_partial(&signature, args.len()) {
+            let remaining = FunctionType {
+                params: signature.params[args.len()..].to_vec(),
+                variadic: false,
+                return_type: signature.return_type,
+            };
+            let kind = TypedExprKind::Partial { callee: Box::new(callee), args };
+            return TypedExpr { kind, ty: Type::Function(remaining), span };
+        }
+        TypedExpr { kind: TypedExprKind::Apply { callee: Box::new(callee), args }, ty: *signature.return_type, span }
+    }
+
+    /// Calls the value of an expression. A partial application of a declared function that is applied right away is
+    /// a single call, e.g. `x |> add(1)` is `add(1, x)`.
+    fn check_apply(&self, callee: Expr, args: Vec<Expr>, span: Span, scope: &FunctionScope) -> TypedExpr {
+        match callee.kind {
+            ExprKind::Call { callee: function, args: first } if self.is_saturated(&function, first.len(), args.len(), scope) => {
+                self.check_call(function, first.into_iter().chain(args).collect(), span, scope)
+            },
+            kind => {
+                let callee = self.check_expression(Expr { kind, span: callee.span }, scope);
+                let args = args.into_iter()
+                    .map(|arg| self.check_expression(arg, scope))
+                    .collect();
+                self.check_value_call(callee, args, span)
+            },
+        }
+    }
+
+    /// Whether a partial application of the declared function with `first` arguments takes `then` more.
+    fn is_saturated(&self, function: &Ident, first: usize, then: usize, scope: &FunctionScope) -> bool {
+        if scope.lookup(&function.name).is_some() {
+            return false;
+        }
+        self.functions.get(&function.name)
+            .is_some_and(|signature| self.is_partial(signature, first) && first + then <= signature.params.len())
+    }
+
+    /// Whether a call with `args` arguments leaves parameters for a later call. A call without any argument is not
+    /// partial, it is missing its arguments.
+    fn is_partial(&self, signature: &FunctionType, args: usize) -> bool {
+        !signature.variadic && args > 0 && args < signature.params.len()
+    }
+
+    fn expect_operand(&self, operator: &str, expected: &Type, operand: &TypedExpr) {
+        if !self.unify(expected, &operand.ty) {
+            self.report(self.error(format!(
+                "operator '{}' expects {} operands, found {}", operator, expected, self.resolve(&operand.ty)
+            ), operand.span));
+        }
+    }
+
+    /// Replaces all inferred type variables and reports anything that could not be inferred.
+    fn finalize(&self, mut program: TypedProgram) -> TypedProgram {
+        for function in program.functions.iter_mut() {
+            self.finalize_signature(&function.name, &mut function.params, &mut function.return_type, function.span);
+            self.finalize_statements(&mut function.body);
+        }
+        if let Entry::Script(statements) = &mut program.entry {
+            self.finalize_statements(statements);
+        }
+        program
+    }
+
+    fn finalize_signature(&self, name: &str, params: &mut [TypedParam], return_type: &mut Type, span: Span) {
+        for param in params.iter_mut() {
+            param.ty = self.resolve_complete(&param.ty).unwrap_or_else(|| {
+                self.report(self.error(format!(
+                    "cannot infer the type of parameter '{}' of '{}'", param.name, name
+                ), param.span).with_help(format!("add a type annotation, e.g. `{}: int`", param.name)));
+                Type::Error
+            });
+        }
+        *return_type = self.resolve_complete(return_type).unwrap_or_else(|| {
+            self.report(self.error(format!("cannot infer the return type of '{}'", name), span)
+                .with_help("add a return type annotation, e.g. `-> int`"));
+            Type::Error
+        });
+    }
+
+    fn finalize_statements(&self, statements: &mut [TypedStmt]) {
+        for statement in statements.iter_mut() {
+            match &mut statement.kind {
+                TypedStmtKind::Define { name, value, .. } => {
+                    self.finalize_expression(value);
+                    if value.ty.is_unit() {
+                        self.report(self.error(format!("cannot assign a value of type unit to '{}'", name), value.span));
+                    }
+                },
+                TypedStmtKind::Assign { value, .. } | TypedStmtKind::AssignField { value, .. }
+                | TypedStmtKind::Expression(value) | TypedStmtKind::Return(Some(value)) => {
+                    self.finalize_expression(value)
+                },
+                TypedStmtKind::While { condition, body } => {
+                    self.finalize_expression(condition);
+                    self.finalize_statements(&mut body.statements);
+                },
+                TypedStmtKind::For { start, end, body, .. } => {
+                    self.finalize_expression(start);
+                    self.finalize_expression(end);
+                    self.finalize_statements(&mut body.statements);
+                },
+                TypedStmtKind::Block(block) => self.finalize_statements(&mut block.statements),
+                TypedStmtKind::Return(None) | TypedStmtKind::Break | TypedStmtKind::Continue => {},
+            }
+        }
+    }
+
+    fn finalize_expression(&self, expression: &mut TypedExpr) {
+        match &mut expression.kind {
+            TypedExprKind::Literal(_) | TypedExprKind::Variable(_) | TypedExprKind::Function(_) => {},
+            TypedExprKind::Call { function, args } => {
+                let fixed_params = self.functions[function.as_str()].params.len();
+                for (position, arg) in args.iter_mut().enumerate() {
+                    self.finalize_expression(arg);
+                    if arg.ty.is_unit() {
+                        self.report(self.error(format!("argument {} of '{}' has no value", position + 1, function), arg.span));
+                    }
+                    if position < fixed_params {
+                        continue;
+                    }
+                    if let Type::Struct(_) | Type::Function(_) = arg.ty {
+                        self.report(self.error(format!("argument {} of '{}' cannot be passed to c, found {}", position + 1, function, arg.ty), arg.span)
+                            .with_help("pass its fields or results as separate values"));
+                    }
+                }
+            },
+            TypedExprKind::Apply { callee, args } | TypedExprKind::Partial { callee, args } => {
+                self.finalize_expression(callee);
+                for (position, arg) in args.iter_mut().enumerate() {
+                    self.finalize_expression(arg);
+                    if arg.ty.is_unit() {
+                        self.report(self.error(format!("argument {} of this call has no value", position + 1), arg.span));
+                    }
+                }
+            },
+            TypedExprKind::Unary { operand, .. } | TypedExprKind::Field { value: operand, .. } => self.finalize_expression(operand),
+            TypedExprKind::StructLiteral { fields, .. } => {
+                for (_, value) in fields.iter_mut() {
+                    self.finalize_expression(value);
+                }
+            },
+            TypedExprKind::Binary { lhs, rhs, .. } => {
+                self.finalize_expression(lhs);
+                self.finalize_expression(rhs);
+            },
+            TypedExprKind::If { condition, then_block, else_block } => {
+                self.finalize_expression(condition);
+                self.finalize_statements(&mut then_block.statements);
+                if let Some(else_block) = else_block {
+                    self.finalize_statements(&mut else_block.statements);
+                }
+            },
+            TypedExprKind::Lambda { params, return_type, body, .. } => {
+                self.finalize_signature("lambda", params, return_type, expression.span);
+                self.finalize_statements(body);
+                // the signature is already reported if it cannot be inferred
+                expression.ty = Type::Function(FunctionType {
+                    params: params.iter().map(|param| param.ty.clone()).collect(),
+                    variadic: false,
+                    return_type: Box::new(return_type.clone()),
+                });
+                return;
+            },
+        }
+
+        expression.ty = self.resolve_complete(&expression.ty).unwrap_or_else(|| {
+            self.report(self.error("cannot infer the type of this expression".to_string(), expression.span));
+            Type::Error
+        });
+    }
+
+    fn fresh_var(&self) -> Type {
+        let mut substitution = self.substitution.borrow_mut();
+        substitution.push(None);
+        Type::Var(substitution.len() as u32 - 1)
+    }
+
+    /// Makes both types equal by binding type variables, returns false if the types are incompatible.
+    /// Anything is compatible with [`Type::Error`], so a reported error does not cause follow up errors.
+    fn unify(&self, expected: &Type, found: &Type) -> bool {
+        match (self.shallow_resolve(expected), self.shallow_resolve(found)) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            (Type::Var(expected), Type::Var(found)) if expected == found => true,
+            (Type::Var(var), other) | (other, Type::Var(var)) => {
+                // a function taking or returning itself has no finite type
+                if self.occurs(var, &other) {
+                    return false;
+                }
+                self.substitution.borrow_mut()[var as usize] = Some(other);
+                true
+            },
+            (Type::Function(expected), Type::Function(found)) => {
+                expected.params.len() == found.params.len()
+                    && expected.params.iter().zip(&found.params).all(|(expected, found)| self.unify(expected, found))
+                    && self.unify(&expected.return_type, &found.return_type)
+            },
+            (expected, found) => expected == found,
+        }
+    }
+
+    fn shallow_resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.substitution.borrow()[var as usize] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn occurs(&self, var: u32, ty: &Type) -> bool {
+        match self.shallow_resolve(ty) {
+            Type::Var(other) => other == var,
+            Type::Function(signature) => {
+                signature.params.iter().any(|param| self.occurs(var, param)) || self.occurs(var, &signature.return_type)
+            },
+            _ => false,
+        }
+    }
+
+    /// The type with all known type variables replaced, for error messages.
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow_resolve(ty) {
+            Type::Function(signature) => Type::Function(FunctionType {
+                params: signature.params.iter().map(|param| self.resolve(param)).collect(),
+                variadic: signature.variadic,
+                return_type: Box::new(self.resolve(&signature.return_type)),
+            }),
+            other => other,
+        }
+    }
+
+    fn resolve_complete(&self, ty: &Type) -> Option<Type> {
+        match self.shallow_resolve(ty) {
+            Type::Var(_) => None,
+            Type::Function(signature) => Some(Type::Function(FunctionType {
+                params: signature.params.iter()
+                    .map(|param| self.resolve_complete(param))
+                    .collect::<Option<Vec<Type>>>()?,
+                variadic: signature.variadic,
+                return_type: Box::new(self.resolve_complete(&signature.return_type)?),
+            })),
+            other => Some(other),
+        }
+    }
+
+    fn error(&self, message: String, span: Span) -> TypeError {
+        TypeError::new(message, span, self.input)
+    }
+
+    fn report(&self, error: TypeError) {
+        self.errors.borrow_mut().push(error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_to_ast;
+
+    fn check(input: &str) -> Result<TypedProgram, Vec<TypeError>> {
+        check_source(parse_to_ast(input).unwrap(), input)
+    }
+
+    fn positions(errors: &[TypeError]) -> Vec<(usize, usize)> {
+        errors.iter().map(|error| (error.line, error.column)).collect()
+    }
+
+    #[test]
+    fn it_infers_parameter_types_from_usage() {
+        let program = check("entry: main\n\nfunc main() {\n\tprintf(\"%ld\", add(1, 2))\n}\n\nfunc add(a, b) {\n\ta + b\n}\n").unwrap();
+
+        let add = program.functions.iter().find(|function| function.name == "add").unwrap();
+        let params: Vec<&Type> = add.params.iter().map(|param| &param.ty).collect();
+        assert_eq!(vec![&Type::Int, &Type::Int], params);
+        assert_eq!(Type::Int, add.return_type);
+    }
+
+    #[test]
+    fn it_reports_mismatched_argument_with_position() {
+        let error = check("entry: main\n\nfunc main() {\n\tgreet(42)\n}\n\nfunc greet(name: str) {\n\tprintf(\"Hi %s\", name)\n}\n").unwrap_err();
+
+        assert_eq!(vec![(4, 8)], positions(&error));
+    }
+
+    #[test]
+    fn it_rejects_arithmetic_on_strings() {
+        let error = check("name = \"chuck\" + 1\n").unwrap_err();
+
+        assert_eq!(vec![(1, 8)], positions(&error));
+    }
+
+    #[test]
+    fn it_reports_all_errors() {
+        let errors = check("x = 1\nx = unknown(y)\nprintf(\"%ld\", x + z)\n").unwrap_err();
+
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "cannot assign twice to immutable variable 'x'",
+            "usage of unknown variable 'y'",
+            "unknown function 'unknown'",
+            "usage of unknown variable 'z'",
+        ], messages);
+    }
+
+    #[test]
+    fn it_rejects_arguments_without_value() {
+        let errors = check("entry: main\n\nfunc main() {\n\tshow(nothing())\n}\n\nfunc nothing() {\n}\n\nfunc show(x) {\n}\n").unwrap_err();
+
+        assert_eq!("argument 1 of 'show' has no value", errors[0].message);
+        assert_eq!(vec![(4, 7)], positions(&errors));
+    }
+
+    #[test]
+    fn only_mutable_variables_can_be_assigned() {
+        let program = check("var total = 0\nfor i in 0..3 {\n\ttotal = total + i\n}\n").unwrap();
+        let Entry::Script(statements) = &program.entry else { panic!("expected a script") };
+        assert!(matches!(statements[0].kind, TypedStmtKind::Define { mutable: true, .. }));
+
+        let errors = check("let limit = 3\nlimit = 4\nvar count = 0\ncount = \"one\"\nlet count = 1\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "cannot assign twice to immutable variable 'limit'",
+            "cannot assign str to variable 'count' of type int",
+            "variable 'count' is already defined",
+        ], messages);
+        assert_eq!(Some(("'limit' is defined here".to_string(), Span::new(4, 9))), errors[0].note);
+    }
+
+    #[test]
+    fn blocks_may_shadow_outer_variables() {
+        let program = check("let x = 1\nif true {\n\tlet x = \"inner\"\n\tprintf(\"%s\", x)\n}\ny = x + 1\n").unwrap();
+        let Entry::Script(statements) = &program.entry else { panic!("expected a script") };
+        assert!(matches!(&statements[2].kind, TypedStmtKind::Define { value, .. } if value.ty == Type::Int));
+
+        let errors = check("if true {\n\tinner = 1\n}\nprintf(\"%ld\", inner)\n").unwrap_err();
+        assert_eq!(vec![(4, 15)], positions(&errors));
+    }
+
+    #[test]
+    fn parameters_can_be_shadowed_by_the_body() {
+        check("entry: main\n\nfunc main() {\n\tprintf(\"%ld\", next(1))\n}\n\nfunc next(n: int) -> int {\n\tlet n = n + 1\n\tn\n}\n").unwrap();
+    }
+
+    #[test]
+    fn externs_are_called_with_their_declared_signature() {
+        let program = check("extern func labs(n: clong) -> clong\nextern func puts(s: str)\n\nputs(\"hi\")\nx = labs(-1)\n").unwrap();
+        assert_eq!(2, program.externs.len());
+        assert!(!program.externs[0].variadic);
+
+        let errors = check("extern func printf(format: str, ...)\nextern func labs(n: clong) -> clong\nlabs(\"one\")\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "function 'printf' is already defined",
+            "argument 1 of 'labs' expects int, found str",
+        ], messages);
+    }
+
+    #[test]
+    fn c_ints_are_ints_for_the_program() {
+        let program = check("extern func abs(n: cint) -> cint\nx = abs(-1) + 1\n").unwrap();
+        assert_eq!(vec![ExternType::CInt], program.externs[0].params);
+        assert_eq!(ExternType::CInt, program.externs[0].return_type);
+
+        let errors = check("entry: main\n\nextern func labs(n: int) -> clong\n\nfunc main() {\n}\n\nfunc half(n: cint) {\n}\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "an `int` of the program is not a c `int`",
+            "unknown type 'cint'",
+        ], messages);
+        assert_eq!(vec![(3, 21), (8, 14)], positions(&errors));
+    }
+
+    #[test]
+    fn structs_and_functions_are_not_passed_to_c() {
+        let errors = check("struct Point {\n\tx: int\n}\n\nextern func mirror(p: Point) -> Point\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "struct 'Point' cannot be passed to or from c functions",
+            "struct 'Point' cannot be passed to or from c functions",
+        ], messages);
+
+        let errors = check("entry: main\n\nstruct Point {\n\tx: int\n}\n\nfunc main() {\n\tlet p = Point { x: 1 }\n\tprintf(\"%ld\", p, main)\n}\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "argument 2 of 'printf' cannot be passed to c, found Point",
+            "argument 3 of 'printf' cannot be passed to c, found fn() -> unit",
+        ], messages);
+    }
+
+    #[test]
+    fn calls_with_missing_arguments_are_partial_applications() {
+        let program = check("entry: main\n\nfunc main() {\n\tapply(add(1), 2)\n}\n\nfunc add(a: int, b: int) -> int {\n\ta + b\n}\n\nfunc apply(f, x) {\n\tf(x)\n}\n").unwrap();
+
+        let apply = program.functions.iter().find(|function| function.name == "apply").unwrap();
+        let increment = FunctionType { params: vec![Type::Int], variadic: false, return_type: Box::new(Type::Int) };
+        assert_eq!(Type::Function(increment), apply.params[0].ty);
+        assert_eq!(Type::Int, apply.return_type);
+    }
+
+    #[test]
+    fn it_reports_arity_errors_of_function_values() {
+        let errors = check("entry: main\n\nfunc main() {\n\tlet increment = add(1)\n\tincrement(1, 2)\n\tincrement(true)\n\tadd()\n}\n\nfunc add(a: int, b: int) -> int {\n\ta + b\n}\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "'increment' expects 1 arguments, but 2 were given",
+            "argument 1 of 'increment' expects int, found bool",
+            "'add' expects 2 arguments, but 0 were given",
+        ], messages);
+    }
+
+    #[test]
+    fn lambdas_capture_the_variables_they_use() {
+        let program = check("offset = 1\nunused = 2\nshift = |x| |y| x + y + offset\n").unwrap();
+
+        let lambda = match &program.entry {
+            Entry::Script(statements) => match &statements[2].kind {
+                TypedStmtKind::Define { value, .. } => value,
+                kind => panic!("unexpected statement {:?}", kind),
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        };
+        match &lambda.kind {
+            TypedExprKind::Lambda { captures, .. } => assert_eq!(vec!["offset".to_string()], *captures),
+            kind => panic!("unexpected expression {:?}", kind),
+        }
+        assert_eq!("fn(int) -> fn(int) -> int", lambda.ty.to_string());
+    }
+
+    #[test]
+    fn captured_variables_cannot_be_assigned() {
+        let errors = check("var count = 0\nbump = || {\n\tcount = count + 1\n}\n").unwrap_err();
+
+        assert_eq!("cannot assign to captured variable 'count'", errors[0].message);
+        assert_eq!(vec![(3, 2)], positions(&errors));
+    }
+
+    #[test]
+    fn piping_into_a_partial_application_is_one_call() {
+        let program = check("entry: main\n\nfunc main() {\n\tprintf(\"%ld\", 1 |> add(2))\n}\n\nfunc add(a: int, b: int) -> int {\n\ta + b\n}\n").unwrap();
+
+        let main = program.functions.iter().find(|function| function.name == "main").unwrap();
+        let args = match &main.body[0].kind {
+            TypedStmtKind::Return(Some(TypedExpr { kind: TypedExprKind::Call { args, .. }, .. })) => args,
+            kind => panic!("unexpected statement {:?}", kind),
+        };
+        match &args[1].kind {
+            TypedExprKind::Call { function, args } => {
+                assert_eq!("add", function);
+                assert_eq!(2, args.len());
+            },
+            kind => panic!("unexpected expression {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn it_checks_conditions_and_branches() {
+        let errors = check("x = if 1 { 2 } else { \"two\" }\ny = !x && true\n").unwrap_err();
+
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "condition of `if` must be bool, found int",
+            "`if` and `else` have incompatible types, int and str",
+            "operator '!' expects bool operands, found int",
+        ], messages);
+    }
+
+    #[test]
+    fn returning_branches_need_no_trailing_value() {
+        let program = check("entry: main\n\nfunc main() {\n\tprintf(\"%ld\", abs(-1))\n}\n\nfunc abs(n) {\n\tif n < 0 {\n\t\treturn -n\n\t} else {\n\t\treturn n\n\t}\n}\n").unwrap();
+
+        let abs = program.functions.iter().find(|function| function.name == "abs").unwrap();
+        assert_eq!(Type::Int, abs.return_type);
+    }
+
+    #[test]
+    fn it_rejects_loop_jumps_outside_of_loops() {
+        let errors = check("for i in 0..\"ten\" {\n\tcontinue\n\tprintf(\"%ld\", i)\n}\nwhile 1 {\n}\nbreak\n").unwrap_err();
+
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "bounds of a range must be int, found str",
+            "unreachable statement after break or continue",
+            "condition of `while` must be bool, found int",
+            "`break` outside of a loop",
+        ], messages);
+    }
+
+    #[test]
+    fn loop_counter_is_scoped_to_the_loop() {
+        let errors = check("for i in 0..3 {\n\tprintf(\"%ld\", i)\n}\nprintf(\"%ld\", i)\n").unwrap_err();
+
+        assert_eq!(vec![(4, 15)], positions(&errors));
+    }
+
+    #[test]
+    fn struct_fields_are_resolved_in_declaration_order() {
+        let program = check("struct Person { name: str, age: int }\nvar ada = Person { age: 36, name: \"Ada\" }\nada.age = ada.age + 1\n").unwrap();
+
+        assert_eq!(vec![("name".to_string(), Type::Str), ("age".to_string(), Type::Int)], program.structs[0].fields);
+        match &program.entry {
+            Entry::Script(statements) => {
+                match &statements[0].kind {
+                    TypedStmtKind::Define { value: TypedExpr { kind: TypedExprKind::StructLiteral { fields, .. }, .. }, .. } => {
+                        assert_eq!(vec![1, 0], fields.iter().map(|(index, _)| *index).collect::<Vec<_>>());
+                    },
+                    kind => panic!("unexpected statement {:?}", kind),
+                }
+                assert!(matches!(&statements[1].kind, TypedStmtKind::AssignField { path, .. } if *path == vec![1]));
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn it_reports_unknown_and_missing_fields() {
+        let errors = check("struct Point { x: int, y: int }\nstruct Node { next: Node }\nlet p = Point { x: 1, z: 2 }\np.x = p.z\n").unwrap_err();
+
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(vec![
+            "struct 'Node' contains itself, so its size is infinite",
+            "struct 'Point' has no field 'z'",
+            "struct literal of 'Point' is missing field 'y'",
+            "struct 'Point' has no field 'z'",
+            "cannot assign to field of immutable variable 'p'",
+        ], messages);
+    }
+}
@@ -0,0 +1,198 @@
+//! The type checked program handed to code generation. Every expression carries its inferred type.
+
+use crate::parser::Span;
+pub use crate::parser::ast::{BinaryOp, Literal, UnaryOp};
+use crate::typeck::types::{FunctionType, StructType, Type};
+
+#[derive(Debug)]
+pub struct TypedProgram {
+    pub externs: Vec<TypedExtern>,
+    /// Structs declared by the program, structs of earlier repl inputs are not repeated.
+    pub structs: Vec<StructType>,
+    pub functions: Vec<TypedFunction>,
+    pub entry: Entry,
+}
+
+#[derive(Debug)]
+pub enum Entry {
+    /// Statements of a script, executed as the body of `main`.
+    Script(Vec<TypedStmt>),
+    /// A declared function called by `main`.
+    Function(String),
+}
+
+#[derive(Debug)]
+pub struct TypedFunction {
+    pub name: String,
+    pub params: Vec<TypedParam>,
+    pub return_type: Type,
+    pub body: Vec<TypedStmt>,
+    pub span: Span,
+}
+
+/// A foreign function, declared but not defined by the program.
+#[derive(Debug)]
+pub struct TypedExtern {
+    pub name: String,
+    pub params: Vec<ExternType>,
+    pub variadic: bool,
+    pub return_type: ExternType,
+}
+
+/// A parameter or return type of an extern, as the c function sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternType {
+    /// Passed like curry passes values of the type.
+    Value(Type),
+    /// A 32 bit c `int`. The program sees an `int`, which is truncated when passed and sign extended when returned.
+    CInt,
+    /// A 64 bit c `long`, the same as an `int` of the program.
+    CLong,
+}
+
+impl TypedExtern {
+    /// The signature the program calls the extern with.
+    pub fn signature(&self) -> FunctionType {
+        FunctionType {
+            params: self.params.iter().map(ExternType::curry_type).collect(),
+            variadic: self.variadic,
+            return_type: Box::new(self.return_type.curry_type()),
+        }
+    }
+}
+
+impl ExternType {
+    pub fn curry_type(&self) -> Type {
+        match self {
+            ExternType::Value(ty) => ty.clone(),
+            ExternType::CInt | ExternType::CLong => Type::Int,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TypedParam {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct TypedStmt {
+    pub kind: TypedStmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TypedStmtKind {
+    /// Introduces a new variable.
+    Define { name: String, mutable: bool, value: TypedExpr },
+    /// Stores to an existing mutable variable.
+    Assign { name: String, value: TypedExpr },
+    /// Stores to a field of a mutable struct variable, `path` holds the field indices from the variable to the field.
+    AssignField { name: String, path: Vec<usize>, value: TypedExpr },
+    Expression(TypedExpr),
+    /// Explicit returns and the trailing expression of a function.
+    Return(Option<TypedExpr>),
+    While { condition: TypedExpr, body: TypedBlock },
+    /// Counts the int `variable` from `start` up to, but excluding, `end`, which is evaluated once.
+    For { variable: String, start: TypedExpr, end: TypedExpr, body: TypedBlock },
+    /// Jumps to the end of the innermost loop.
+    Break,
+    /// Jumps to the next iteration of the innermost loop.
+    Continue,
+    Block(TypedBlock),
+}
+
+#[derive(Debug)]
+pub struct TypedExpr {
+    pub kind: TypedExprKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TypedExprKind {
+    Literal(Literal),
+    Variable(String),
+    /// A declared function used as a value.
+    Function(String),
+    /// Calls a declared function with all its arguments.
+    Call { function: String, args: Vec<TypedExpr> },
+    /// Calls a function value with all its remaining arguments.
+    Apply { callee: Box<TypedExpr>, args: Vec<TypedExpr> },
+    /// Calls a function value with fewer arguments than it takes, the result is a function value of the remaining
+    /// parameters capturing the given arguments.
+    Partial { callee: Box<TypedExpr>, args: Vec<TypedExpr> },
+    Unary { op: UnaryOp, operand: Box<TypedExpr> },
+    Binary { op: BinaryOp, lhs: Box<TypedExpr>, rhs: Box<TypedExpr> },
+    /// If the `if` has a value, it is the trailing expression of the executed block.
+    If { condition: Box<TypedExpr>, then_block: TypedBlock, else_block: Option<TypedBlock> },
+    /// An anonymous function with the variables of the enclosing functions it uses, which it captures by value.
+    Lambda { params: Vec<TypedParam>, return_type: Type, body: Vec<TypedStmt>, captures: Vec<String> },
+    /// A struct value, the fields are given by index and evaluated in source order.
+    StructLiteral { name: String, fields: Vec<(usize, TypedExpr)> },
+    /// Reads the field with the index from a struct value.
+    Field { value: Box<TypedExpr>, index: usize },
+}
+
+#[derive(Debug)]
+pub struct TypedBlock {
+    pub statements: Vec<TypedStmt>,
+}
+
+impl TypedStmt {
+    /// Whether control never continues after the statement, because it returns on every path.
+    pub fn diverges(&self) -> bool {
+        match &self.kind {
+            TypedStmtKind::Return(_) => true,
+            TypedStmtKind::Define { value, .. } | TypedStmtKind::Assign { value, .. }
+            | TypedStmtKind::AssignField { value, .. } | TypedStmtKind::Expression(value) => {
+                value.diverges()
+            },
+            // a loop may be left by `break` or never entered
+            TypedStmtKind::While { .. } | TypedStmtKind::For { .. } => false,
+            TypedStmtKind::Break | TypedStmtKind::Continue => false,
+            TypedStmtKind::Block(block) => block.diverges(),
+        }
+    }
+
+    /// Whether control never continues after the statement, because it returns or jumps out of a loop on every path.
+    pub fn interrupts(&self) -> bool {
+        match &self.kind {
+            TypedStmtKind::Return(_) | TypedStmtKind::Break | TypedStmtKind::Continue => true,
+            TypedStmtKind::Define { value, .. } | TypedStmtKind::Assign { value, .. }
+            | TypedStmtKind::AssignField { value, .. } | TypedStmtKind::Expression(value) => {
+                value.interrupts()
+            },
+            TypedStmtKind::While { .. } | TypedStmtKind::For { .. } => false,
+            TypedStmtKind::Block(block) => block.interrupts(),
+        }
+    }
+}
+
+impl TypedExpr {
+    pub fn diverges(&self) -> bool {
+        match &self.kind {
+            TypedExprKind::If { then_block, else_block: Some(else_block), .. } => then_block.diverges() && else_block.diverges(),
+            _ => false,
+        }
+    }
+
+    pub fn interrupts(&self) -> bool {
+        match &self.kind {
+            TypedExprKind::If { then_block, else_block: Some(else_block), .. } => then_block.interrupts() && else_block.interrupts(),
+            _ => false,
+        }
+    }
+}
+
+impl TypedBlock {
+    pub fn diverges(&self) -> bool {
+        self.statements.iter().any(TypedStmt::diverges)
+    }
+
+    pub fn interrupts(&self) -> bool {
+        self.statements.iter().any(TypedStmt::interrupts)
+    }
+}
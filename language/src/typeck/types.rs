@@ -0,0 +1,83 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Str,
+    Int,
+    Bool,
+    /// The type of expressions without a value, e.g. a call of a function without return value.
+    Unit,
+    /// A function value, e.g. a partially applied function. Never variadic.
+    Function(FunctionType),
+    /// A value of the declared struct with this name.
+    Struct(String),
+    /// The type of erroneous expressions, compatible with everything to avoid follow up errors.
+    Error,
+    /// Placeholder for a type that is not inferred yet. Never part of a checked program.
+    Var(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    pub params: Vec<Type>,
+    /// Accepts any number of additional arguments after `params`, like `printf`.
+    pub variadic: bool,
+    pub return_type: Box<Type>,
+}
+
+/// A declared struct, its fields in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+}
+
+impl StructType {
+    /// Position and type of the field.
+    pub fn field(&self, name: &str) -> Option<(usize, &Type)> {
+        self.fields.iter()
+            .enumerate()
+            .find(|(_, (field, _))| field == name)
+            .map(|(index, (_, ty))| (index, ty))
+    }
+}
+
+impl Type {
+    pub fn from_type_name(name: &str) -> Option<Type> {
+        match name {
+            "str" => Some(Type::Str),
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            _ => None,
+        }
+    }
+
+    pub fn is_unit(&self) -> bool {
+        matches!(self, Type::Unit)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Str => write!(f, "str"),
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Unit => write!(f, "unit"),
+            Type::Function(signature) => write!(f, "{}", signature),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Error => write!(f, "{{error}}"),
+            Type::Var(_) => write!(f, "_"),
+        }
+    }
+}
+
+impl fmt::Display for FunctionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params: Vec<String> = self.params.iter().map(|it| it.to_string()).collect();
+        if self.variadic {
+            params.push("...".to_string());
+        }
+        write!(f, "fn({}) -> {}", params.join(", "), self.return_type)
+    }
+}
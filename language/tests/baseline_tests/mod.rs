@@ -23,6 +23,66 @@ fn minimal_program() {
 fn return_values() {
     run_baseline_test("return_values");
 }
+#[test]
+fn arithmetic() {
+    run_baseline_test("arithmetic");
+}
+#[test]
+fn parameters() {
+    run_baseline_test("parameters");
+}
+#[test]
+fn type_inference() {
+    run_baseline_test("type_inference");
+}
+#[test]
+fn conditionals() {
+    run_baseline_test("conditionals");
+}
+#[test]
+fn loops() {
+    run_baseline_test("loops");
+}
+#[test]
+fn mutability() {
+    run_baseline_test("mutability");
+}
+#[test]
+fn scoping() {
+    run_baseline_test("scoping");
+}
+#[test]
+fn comments() {
+    run_baseline_test("comments");
+}
+#[test]
+fn escapes() {
+    run_baseline_test("escapes");
+}
+#[test]
+fn externs() {
+    run_baseline_test("externs");
+}
+#[test]
+fn currying() {
+    run_baseline_test("currying");
+}
+#[test]
+fn lambdas() {
+    run_baseline_test("lambdas");
+}
+#[test]
+fn pipeline() {
+    run_baseline_test("pipeline");
+}
+#[test]
+fn nested_calls() {
+    run_baseline_test("nested_calls");
+}
+#[test]
+fn structs() {
+    run_baseline_test("structs");
+}
 
 
 
@@ -40,15 +100,7 @@ fn run_baseline_test(key: &str) {
     let actual_output = &format!("{}.actual.{}", key.to_owned(), "out");
     let actual_errors = &format!("{}.actual.{}", key.to_owned(), "err");
 
-    generate_code_for_source(input, ir_path);
-
-
-    Command::new("clang")
-        .current_dir(test_dir)
-        .arg(ir_file)
-        .args(["-o", binary])
-        .status()
-        .unwrap_or_else(|e| panic!("failed to compile generated llir\n{}", e));
+    generate_code_for_source(input, ir_path, test_dir.join(binary));
 
     Command::new(format!("./{}", binary))
         .current_dir(test_dir)
@@ -61,13 +113,14 @@ fn run_baseline_test(key: &str) {
     diff_output(test_dir, key, "out", actual_output);
 }
 
-fn generate_code_for_source(input: PathBuf, ir_path: PathBuf) {
+fn generate_code_for_source(input: PathBuf, ir_path: PathBuf, binary: PathBuf) {
     let context = Context::create();
     let mut code_gen = LLIRCodeGenerator::new(&context);
     code_gen.compile_source_file(input).unwrap();
-    let modules = &mut code_gen.modules;
-    assert_eq!(1, modules.len());
-    modules.remove("main").unwrap().print_to_file(ir_path).unwrap();
+    assert_eq!(1, code_gen.modules.len());
+    code_gen.main_module().unwrap().print_to_file(ir_path).unwrap();
+    code_gen.build_executable(binary)
+        .unwrap_or_else(|e| panic!("failed to build executable\n{}", e));
 }
 
 fn diff_output(test_dir: &Path, key: &str, output_type: &str, actual: &str) {
@@ -105,7 +158,7 @@ fn get_output(path: &Path, file: &str) -> Stdio {
         .open(path.join(file))
         .unwrap();
 
-    return Stdio::from(file);
+    Stdio::from(file)
 }
 
 macro_rules! _run_baseline_test {
@@ -0,0 +1,112 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+#[test]
+fn check_accepts_valid_source() {
+    let output = curryc(&["check"], "calumni");
+    assert_eq!(Some(0), output.status.code());
+}
+
+#[test]
+fn check_reports_source_errors() {
+    let source = std::env::temp_dir().join(format!("curryc-check-{}.cry", std::process::id()));
+    std::fs::write(&source, "x = 1 +\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_curryc"))
+        .arg("check")
+        .arg(&source)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&source).unwrap();
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: syntax error"));
+}
+
+#[test]
+fn emit_llvm_writes_to_stdout() {
+    let output = curryc(&["emit-llvm", "-o", "-"], "hello_world");
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("define i32 @main()"));
+}
+
+#[test]
+fn emit_llvm_optimizes_with_opt_level() {
+    let output = curryc(&["emit-llvm", "-O2", "-o", "-"], "calumni");
+    assert_eq!(Some(0), output.status.code());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("alloca"));
+}
+
+#[test]
+fn run_exits_with_program_status() {
+    let output = curryc(&["run"], "minimal_program");
+    assert_eq!(Some(0), output.status.code());
+}
+
+#[test]
+fn build_does_not_overwrite_source_without_extension() {
+    let dir = std::env::temp_dir().join(format!("curryc-build-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = "printf(\"built\")\n";
+    std::fs::write(dir.join("prog"), source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_curryc"))
+        .arg("build")
+        .arg("prog")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let unchanged = std::fs::read_to_string(dir.join("prog")).unwrap() == source;
+    let built = dir.join("prog.out").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(Some(0), output.status.code(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(unchanged);
+    assert!(built);
+}
+
+#[test]
+fn run_with_jit_prints_program_output() {
+    let output = curryc(&["run", "--jit"], "hello_world");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("Hello World! Greetings from curry-lang", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn repl_evaluates_inputs() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_curryc"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap()
+        .write_all(b"func square(n) {\n\tn * n\n}\nx = square(3)\nx + 1\nunknown\n:quit\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("10\n"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage of unknown variable 'unknown'"));
+}
+
+#[test]
+fn missing_input_is_usage_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_curryc")).arg("build").output().unwrap();
+    assert_eq!(Some(2), output.status.code());
+}
+
+fn curryc(args: &[&str], baseline: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_curryc"))
+        .args(args)
+        .arg(baseline_source(baseline))
+        .output()
+        .unwrap_or_else(|e| panic!("failed to invoke curryc\n{}", e))
+}
+
+fn baseline_source(key: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/baseline_tests")
+        .join(key)
+        .join(key.to_owned() + ".cry")
+}
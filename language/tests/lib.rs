@@ -1 +1,2 @@
-mod baseline_tests;
\ No newline at end of file
+mod baseline_tests;
+mod cli_tests;